@@ -0,0 +1,226 @@
+//! Zip archive with a backup of the whole database.
+//!
+//! Next to `db.json` there is a text file for every lyric, with the title on the first line like a plain text upload,
+//! and one for every playlist. The manifest lists every file with its checksum. Only `db.json` is used on restore.
+
+use std::io::{Cursor, Read, Write};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{Db, Result, error::Error, validation::Violation};
+
+pub const CONTENT_TYPE: &str = "application/zip";
+pub const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const DB: &str = "db.json";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Manifest {
+    /// Version of the archive layout
+    pub format: u32,
+    /// Version of the database schema the archive was made with
+    pub schema_version: usize,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ManifestFile {
+    pub name: String,
+    pub size: usize,
+    /// Hex encoded sha256 of the content
+    pub sha256: String,
+}
+
+impl ManifestFile {
+    fn new(name: String, content: &[u8]) -> Self {
+        Self {
+            name,
+            size: content.len(),
+            sha256: checksum(content),
+        }
+    }
+}
+
+fn checksum(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+fn files(db: &Db) -> Result<Vec<(String, Vec<u8>)>> {
+    let lyrics = db.lyrics.iter().map(|lyric| {
+        (
+            format!("lyrics/{}.txt", lyric.id),
+            format!("{}\n\n{}\n", lyric.title, lyric.to_parts().to_text()).into_bytes(),
+        )
+    });
+    let playlists = db.playlists.iter().map(|playlist| {
+        let members = playlist
+            .members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| {
+                let title = db
+                    .lyrics
+                    .iter()
+                    .find(|lyric| lyric.id == member.lyric_id)
+                    .map(|lyric| lyric.title.as_str())
+                    .unwrap_or(&member.lyric_id);
+                format!("{}. {title}\n", i + 1)
+            })
+            .collect::<String>();
+        (
+            format!("playlists/{}.txt", playlist.id),
+            format!("{}\n\n{members}", playlist.title).into_bytes(),
+        )
+    });
+    Ok(
+        std::iter::once((DB.to_owned(), serde_json::to_vec_pretty(db)?))
+            .chain(lyrics)
+            .chain(playlists)
+            .collect(),
+    )
+}
+
+pub fn to_archive(db: &Db, schema_version: usize) -> Result<Vec<u8>> {
+    let files = files(db)?;
+    let manifest = Manifest {
+        format: FORMAT,
+        schema_version,
+        files: files
+            .iter()
+            .map(|(name, content)| ManifestFile::new(name.clone(), content))
+            .collect(),
+    };
+    let options = SimpleFileOptions::default();
+    let mut writer = ZipWriter::new(Cursor::new(vec![]));
+    writer.start_file(MANIFEST, options)?;
+    writer.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    for (name, content) in files {
+        writer.start_file(name, options)?;
+        writer.write_all(&content)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(name).ok()?;
+    let mut content = vec![];
+    file.read_to_end(&mut content).ok()?;
+    Some(content)
+}
+
+/// Checks the manifest and the checksums of the files in it before reading the database.
+/// Archives made with a newer database schema are refused.
+pub fn from_archive(bytes: &[u8], schema_version: usize) -> Result<Db> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|_| Error::Body)?;
+    let manifest = read_file(&mut archive, MANIFEST)
+        .ok_or_else(|| Error::Validation(vec![Violation::new(MANIFEST, "missing")]))?;
+    let manifest = serde_json::from_slice::<Manifest>(&manifest)
+        .map_err(|e| Error::Validation(vec![Violation::new(MANIFEST, e.to_string())]))?;
+
+    let mut violations = vec![];
+    if manifest.format != FORMAT {
+        violations.push(Violation::new("manifest.format", "not supported"));
+    }
+    if manifest.schema_version > schema_version {
+        violations.push(Violation::new(
+            "manifest.schema_version",
+            format!("newer than {schema_version}"),
+        ));
+    }
+    if !manifest.files.iter().any(|file| file.name == DB) {
+        violations.push(Violation::new("manifest.files", format!("{DB} missing")));
+    }
+    for (i, file) in manifest.files.iter().enumerate() {
+        match read_file(&mut archive, &file.name) {
+            Some(content) if checksum(&content) == file.sha256 => {}
+            Some(_) => violations.push(Violation::new(
+                format!("manifest.files[{i}].sha256"),
+                "does not match",
+            )),
+            None => violations.push(Violation::new(
+                format!("manifest.files[{i}].name"),
+                "missing in archive",
+            )),
+        }
+    }
+    if !violations.is_empty() {
+        return Err(Error::Validation(violations));
+    }
+
+    let db = read_file(&mut archive, DB).unwrap_or_default();
+    serde_json::from_slice(&db).map_err(Error::from_body)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::{from_archive, to_archive};
+    use crate::{Db, Lyric, Playlist, error::Error};
+
+    fn db() -> Db {
+        Db {
+            lyrics: vec![
+                Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![])
+                    .with_parts("[G]Zij dronk ranja".parse().unwrap()),
+            ],
+            playlists: vec![Playlist::new(
+                "p".to_owned(),
+                "Kerst".to_owned(),
+                vec!["a".into()],
+            )],
+        }
+    }
+
+    fn fields(error: Error) -> Vec<String> {
+        match error {
+            Error::Validation(violations) => violations.into_iter().map(|v| v.field).collect(),
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let archive = to_archive(&db(), 3).unwrap();
+        assert_eq!(from_archive(&archive, 3).unwrap(), db());
+        assert_eq!(
+            fields(from_archive(&archive, 2).unwrap_err()),
+            vec!["manifest.schema_version"]
+        );
+    }
+
+    #[test]
+    fn tampered() {
+        let archive = to_archive(&db(), 3).unwrap();
+        let mut source = zip::ZipArchive::new(Cursor::new(archive.as_slice())).unwrap();
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for i in 0..source.len() {
+            let file = source.by_index(i).unwrap();
+            let name = file.name().to_owned();
+            if name == "db.json" {
+                drop(file);
+                writer
+                    .start_file(name, SimpleFileOptions::default())
+                    .unwrap();
+                writer
+                    .write_all(br#"{"lyrics":[],"playlists":[]}"#)
+                    .unwrap();
+            } else if name.starts_with("lyrics/") {
+                continue;
+            } else {
+                writer.raw_copy_file(file).unwrap();
+            }
+        }
+        let tampered = writer.finish().unwrap().into_inner();
+        assert_eq!(
+            fields(from_archive(&tampered, 3).unwrap_err()),
+            vec!["manifest.files[0].sha256", "manifest.files[1].name"]
+        );
+        assert!(matches!(from_archive(b"not a zip", 3), Err(Error::Body)));
+    }
+}
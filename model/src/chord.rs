@@ -0,0 +1,393 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Accidental {
+    Sharp,
+    Flat,
+}
+
+/// A note name like `F#` or `Bb`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Note {
+    pub letter: char,
+    pub accidental: Option<Accidental>,
+}
+
+const SHARPS: [(char, Option<Accidental>); 12] = [
+    ('C', None),
+    ('C', Some(Accidental::Sharp)),
+    ('D', None),
+    ('D', Some(Accidental::Sharp)),
+    ('E', None),
+    ('F', None),
+    ('F', Some(Accidental::Sharp)),
+    ('G', None),
+    ('G', Some(Accidental::Sharp)),
+    ('A', None),
+    ('A', Some(Accidental::Sharp)),
+    ('B', None),
+];
+
+const FLATS: [(char, Option<Accidental>); 12] = [
+    ('C', None),
+    ('D', Some(Accidental::Flat)),
+    ('D', None),
+    ('E', Some(Accidental::Flat)),
+    ('E', None),
+    ('F', None),
+    ('G', Some(Accidental::Flat)),
+    ('G', None),
+    ('A', Some(Accidental::Flat)),
+    ('A', None),
+    ('B', Some(Accidental::Flat)),
+    ('B', None),
+];
+
+/// Pitch classes of the major keys that are written with flats: Db, Eb, F, Ab and Bb
+const FLAT_MAJOR_KEYS: [i32; 5] = [1, 3, 5, 8, 10];
+
+impl Note {
+    /// Semitones above C, from 0 up to 11
+    pub fn pitch_class(&self) -> i32 {
+        let natural = SHARPS
+            .iter()
+            .position(|(letter, accidental)| *letter == self.letter && accidental.is_none())
+            .unwrap_or_default() as i32;
+        let offset = match self.accidental {
+            Some(Accidental::Sharp) => 1,
+            Some(Accidental::Flat) => -1,
+            None => 0,
+        };
+        (natural + offset).rem_euclid(12)
+    }
+
+    pub fn from_pitch_class(pitch_class: i32, flats: bool) -> Self {
+        let names = if flats { FLATS } else { SHARPS };
+        let (letter, accidental) = names[pitch_class.rem_euclid(12) as usize];
+        Self { letter, accidental }
+    }
+
+    pub fn transpose(&self, semitones: i32, flats: bool) -> Self {
+        Self::from_pitch_class(self.pitch_class() + semitones, flats)
+    }
+
+    fn parse(s: &str) -> Option<(Note, &str)> {
+        let mut chars = s.chars();
+        let letter = chars.next().filter(|c| ('A'..='G').contains(c))?;
+        let rest = chars.as_str();
+        let (accidental, rest) = if let Some(rest) = rest.strip_prefix('#') {
+            (Some(Accidental::Sharp), rest)
+        } else if let Some(rest) = rest.strip_prefix('b') {
+            (Some(Accidental::Flat), rest)
+        } else {
+            (None, rest)
+        };
+        Some((Note { letter, accidental }, rest))
+    }
+}
+
+impl Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.letter)?;
+        match self.accidental {
+            Some(Accidental::Sharp) => write!(f, "#"),
+            Some(Accidental::Flat) => write!(f, "b"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Building blocks of the chord quality, like the `m7` in `Am7`.
+/// Words like `Chorus` or `Bridge` are not chords, so they can be used as part labels.
+const QUALITY_TOKENS: [&str; 17] = [
+    "maj", "min", "dim", "aug", "sus", "add", "alt", "m", "M", "o", "ø", "°", "+", "-", "#", "b",
+    "^",
+];
+
+fn is_quality(mut s: &str) -> bool {
+    while !s.is_empty() {
+        let trimmed = s.trim_start_matches(|c: char| c.is_ascii_digit() || c == '(' || c == ')');
+        if trimmed.len() < s.len() {
+            s = trimmed;
+        } else if let Some(token) = QUALITY_TOKENS.iter().find(|t| s.starts_with(*t)) {
+            s = &s[token.len()..];
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+/// A chord in ChordPro notation, like `Am7`, `F#` or `C/G`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub root: Note,
+    pub quality: String,
+    pub bass: Option<Note>,
+}
+
+impl FromStr for Chord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (root, rest) = Note::parse(s.trim()).ok_or(Error::Body)?;
+        let (quality, bass) = match rest.rsplit_once('/') {
+            Some((quality, bass)) => match Note::parse(bass) {
+                Some((bass, "")) => (quality, Some(bass)),
+                _ => return Err(Error::Body),
+            },
+            None => (rest, None),
+        };
+        if is_quality(quality) {
+            Ok(Chord {
+                root,
+                quality: quality.to_owned(),
+                bass,
+            })
+        } else {
+            Err(Error::Body)
+        }
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.root, self.quality)?;
+        match self.bass {
+            Some(bass) => write!(f, "/{bass}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Chord {
+    /// Moves root and bass, the quality stays the same
+    pub fn transpose(&self, semitones: i32, flats: bool) -> Self {
+        Self {
+            root: self.root.transpose(semitones, flats),
+            quality: self.quality.clone(),
+            bass: self.bass.map(|bass| bass.transpose(semitones, flats)),
+        }
+    }
+
+    fn is_minor(&self) -> bool {
+        self.quality.starts_with('m') && !self.quality.starts_with("maj")
+    }
+}
+
+/// The key of a song, like `G`, `Bb` or `F#m`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub tonic: Note,
+    pub minor: bool,
+}
+
+impl Key {
+    /// Flat keys are written with flats, sharp keys with sharps.
+    /// A key without accidental is written like its relative major key, so `F` and `Dm` use flats.
+    pub fn flats(&self) -> bool {
+        match self.tonic.accidental {
+            Some(accidental) => accidental == Accidental::Flat,
+            None => FLAT_MAJOR_KEYS.contains(&self.relative_major()),
+        }
+    }
+
+    fn relative_major(&self) -> i32 {
+        (self.tonic.pitch_class() + if self.minor { 3 } else { 0 }).rem_euclid(12)
+    }
+
+    /// The key the given number of semitones higher, spelled the way it is usually written
+    pub fn transpose(&self, semitones: i32) -> Self {
+        let pitch_class = self.tonic.pitch_class() + semitones;
+        let relative_major = (pitch_class + if self.minor { 3 } else { 0 }).rem_euclid(12);
+        Self {
+            tonic: Note::from_pitch_class(pitch_class, FLAT_MAJOR_KEYS.contains(&relative_major)),
+            minor: self.minor,
+        }
+    }
+
+    /// The smallest number of semitones, up or down, from this key to the other
+    pub fn semitones_to(&self, other: &Key) -> i32 {
+        let up = (other.tonic.pitch_class() - self.tonic.pitch_class()).rem_euclid(12);
+        if up > 6 { up - 12 } else { up }
+    }
+}
+
+impl From<&Chord> for Key {
+    fn from(chord: &Chord) -> Self {
+        Self {
+            tonic: chord.root,
+            minor: chord.is_minor(),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    /// Accepts a chord, so `Am7` is in `Am`. The first letter may be lowercase.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut chars = s.chars();
+        let first = chars.next().map(|c| c.to_ascii_uppercase());
+        let chord = first
+            .into_iter()
+            .chain(chars)
+            .collect::<String>()
+            .parse::<Chord>()?;
+        Ok(Key::from(&chord))
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.tonic, if self.minor { "m" } else { "" })
+    }
+}
+
+pub fn is_chord(s: &str) -> bool {
+    s.parse::<Chord>().is_ok()
+}
+
+/// A chord played from the character at `position` in the sung text of a line
+#[derive(Clone, Debug, Deserialize, Hash, Serialize, PartialEq, Eq)]
+pub struct ChordAt {
+    pub position: usize,
+    pub chord: String,
+}
+
+/// Splits a line with inline chords like `[G]Zij dronk [D]ranja` into the sung text and the chords
+pub fn split_chords(line: &str) -> (String, Vec<ChordAt>) {
+    let mut text = String::new();
+    let mut chords = vec![];
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            break;
+        };
+        let candidate = &rest[start + 1..end];
+        text.push_str(&rest[..start]);
+        if is_chord(candidate) {
+            chords.push(ChordAt {
+                position: text.chars().count(),
+                chord: candidate.to_owned(),
+            });
+        } else {
+            text.push_str(&rest[start..=end]);
+        }
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    (text, chords)
+}
+
+/// Puts the chords back into the sung text, the inverse of [`split_chords`]
+pub fn join_chords(text: &str, chords: &[ChordAt]) -> String {
+    let mut line = String::new();
+    let mut chars = text.chars().enumerate().peekable();
+    for chord in chords {
+        while let Some((_, c)) = chars.next_if(|(i, _)| *i < chord.position) {
+            line.push(c);
+        }
+        line.push_str(&format!("[{}]", chord.chord));
+    }
+    line.extend(chars.map(|(_, c)| c));
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Accidental, Chord, ChordAt, Key, Note, is_chord, join_chords, split_chords};
+
+    #[test]
+    fn parse_chords() {
+        assert_eq!(
+            "F#m7/C#".parse::<Chord>().unwrap(),
+            Chord {
+                root: Note {
+                    letter: 'F',
+                    accidental: Some(Accidental::Sharp)
+                },
+                quality: "m7".to_owned(),
+                bass: Some(Note {
+                    letter: 'C',
+                    accidental: Some(Accidental::Sharp)
+                }),
+            }
+        );
+        for chord in [
+            "G", "Am", "Bb", "Dsus4", "Emaj7", "Bm7b5", "C/G", "Cadd9", "G7(b9)",
+        ] {
+            assert!(is_chord(chord), "{chord}");
+            assert_eq!(chord.parse::<Chord>().unwrap().to_string(), chord);
+        }
+        for label in ["Chorus", "Bridge", "Verse 1", "Coda", "Ending", "Intro", ""] {
+            assert!(!is_chord(label), "{label}");
+        }
+    }
+
+    #[test]
+    fn transpose_chords() {
+        let transpose = |chord: &str, semitones, key: &str| {
+            let flats = key.parse::<Key>().unwrap().flats();
+            chord
+                .parse::<Chord>()
+                .unwrap()
+                .transpose(semitones, flats)
+                .to_string()
+        };
+        assert_eq!(transpose("G", 2, "A"), "A");
+        assert_eq!(transpose("D7/F#", 3, "Bb"), "F7/A");
+        assert_eq!(transpose("Em", 1, "F"), "Fm");
+        assert_eq!(transpose("Bbmaj7", 1, "D"), "Bmaj7");
+        assert_eq!(transpose("F#m7b5", -1, "Dm"), "Fm7b5");
+        assert_eq!(transpose("C#", 1, "E"), "D");
+        assert_eq!(transpose("G#dim", 12, "E"), "G#dim");
+    }
+
+    #[test]
+    fn keys() {
+        let key = |s: &str| s.parse::<Key>().unwrap();
+        assert!(key("F").flats());
+        assert!(key("dm").flats());
+        assert!(key("Gm").flats());
+        assert!(!key("D").flats());
+        assert!(!key("C#m").flats());
+        assert_eq!(key("G").transpose(1).to_string(), "Ab");
+        assert_eq!(key("Em").transpose(1).to_string(), "Fm");
+        assert_eq!(key("G").transpose(-1).to_string(), "F#");
+        assert_eq!(key("G").semitones_to(&key("A")), 2);
+        assert_eq!(key("G").semitones_to(&key("Eb")), -4);
+        assert_eq!(key("Am7").to_string(), "Am");
+        assert!("H".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn split_and_join() {
+        let line = "[G]Zij dronk [D7]ranja met een [Chorus]rietje[C]";
+        let (text, chords) = split_chords(line);
+        assert_eq!(text, "Zij dronk ranja met een [Chorus]rietje");
+        assert_eq!(
+            chords,
+            vec![
+                ChordAt {
+                    position: 0,
+                    chord: "G".to_owned()
+                },
+                ChordAt {
+                    position: 10,
+                    chord: "D7".to_owned()
+                },
+                ChordAt {
+                    position: 38,
+                    chord: "C".to_owned()
+                },
+            ]
+        );
+        assert_eq!(join_chords(&text, &chords), line);
+    }
+}
@@ -0,0 +1,294 @@
+//! Songs in [ChordPro](https://www.chordpro.org) format, one or more in a file separated by `{new_song}`.
+//!
+//! Metadata directives map onto [`LyricMeta`], sections like `{start_of_chorus}` onto labeled parts.
+//! The arrangement is written as the custom directive `{x_arrangement:}`, other tools skip it.
+
+use std::iter::once;
+
+use crate::{
+    Lyric, LyricMeta, Uuid,
+    parts::{Part, Parts, directive},
+};
+
+pub const CONTENT_TYPE: &str = "text/x-chordpro; charset=utf-8";
+pub const EXTENSION: &str = ".cho";
+
+const NEW_SONG: &str = "{new_song}";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Chorus,
+    Verse,
+    Bridge,
+}
+
+impl Section {
+    fn from_label(label: &str) -> Self {
+        let label = label.to_lowercase();
+        if label.starts_with("chorus") || label.starts_with("refrein") {
+            Self::Chorus
+        } else if label.starts_with("bridge") {
+            Self::Bridge
+        } else {
+            Self::Verse
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Chorus => "chorus",
+            Self::Verse => "verse",
+            Self::Bridge => "bridge",
+        }
+    }
+
+    fn default_label(&self) -> Option<&'static str> {
+        match self {
+            Self::Chorus => Some("Chorus"),
+            Self::Verse => None,
+            Self::Bridge => Some("Bridge"),
+        }
+    }
+}
+
+/// Duration as `m:ss` or as number of seconds
+fn duration(value: &str) -> Option<u32> {
+    match value.split_once(':') {
+        Some((minutes, seconds)) => {
+            Some(minutes.trim().parse::<u32>().ok()? * 60 + seconds.trim().parse::<u32>().ok()?)
+        }
+        None => value.trim().parse().ok(),
+    }
+}
+
+#[derive(Default)]
+struct Song {
+    title: Option<String>,
+    meta: LyricMeta,
+    parts: Vec<Part>,
+    arrangement: Vec<String>,
+    section: Option<(Section, Option<String>)>,
+    lines: Vec<String>,
+}
+
+impl Song {
+    fn close_part(&mut self) {
+        if !self.lines.is_empty() {
+            let label = self.section.as_ref().and_then(|(section, label)| {
+                label.clone().or(section.default_label().map(String::from))
+            });
+            self.parts.push(Part {
+                label,
+                ..Part::from(std::mem::take(&mut self.lines))
+            });
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.parts.is_empty()
+    }
+
+    fn into_lyric(self) -> Lyric {
+        Lyric {
+            meta: self.meta,
+            ..Lyric::new(
+                Uuid::default().to_string(),
+                self.title.unwrap_or_default(),
+                vec![],
+            )
+            .with_parts(Parts::new(self.parts, self.arrangement))
+        }
+    }
+
+    fn add_line(&mut self, line: &str) {
+        match directive(line) {
+            Some((name, value)) => {
+                let value = value.unwrap_or_default().to_owned();
+                match name.as_str() {
+                    "title" | "t" => self.title = Some(value),
+                    "artist" => self.meta.artist = Some(value),
+                    "composer" => self.meta.composer = Some(value),
+                    "copyright" => self.meta.copyright = Some(value),
+                    "key" => self.meta.key = Some(value),
+                    "tempo" => self.meta.tempo = value.parse().ok(),
+                    "duration" => self.meta.duration = duration(&value),
+                    "x_arrangement" | "arrangement" => {
+                        self.arrangement = value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|label| !label.is_empty())
+                            .map(String::from)
+                            .collect()
+                    }
+                    "start_of_chorus" | "soc" => self.start(Section::Chorus, value),
+                    "start_of_verse" | "sov" => self.start(Section::Verse, value),
+                    "start_of_bridge" | "sob" => self.start(Section::Bridge, value),
+                    "end_of_chorus" | "eoc" | "end_of_verse" | "eov" | "end_of_bridge" | "eob" => {
+                        self.close_part();
+                        self.section = None;
+                    }
+                    _ => {}
+                }
+            }
+            None if line.is_empty() => {
+                // blank lines inside a section belong to the section
+                if self.section.is_none() {
+                    self.close_part();
+                }
+            }
+            None if line.starts_with('#') => {}
+            None => self.lines.push(line.to_owned()),
+        }
+    }
+
+    fn start(&mut self, section: Section, label: String) {
+        self.close_part();
+        self.section = Some((section, Some(label).filter(|l| !l.is_empty())));
+    }
+}
+
+/// Every song in the text, with a new id
+pub fn from_chordpro(text: &str) -> Vec<Lyric> {
+    let mut songs = vec![];
+    let mut song = Song::default();
+    for line in text.lines().map(str::trim) {
+        if matches!(directive(line), Some((name, _)) if name == "new_song" || name == "ns") {
+            song.close_part();
+            songs.push(std::mem::take(&mut song));
+        } else {
+            song.add_line(line);
+        }
+    }
+    song.close_part();
+    songs.push(song);
+    songs
+        .into_iter()
+        .filter(|song| !song.is_empty())
+        .map(Song::into_lyric)
+        .collect()
+}
+
+pub fn to_chordpro(lyric: &Lyric) -> String {
+    let meta = &lyric.meta;
+    let directives = [
+        ("title", Some(lyric.title.clone())),
+        ("artist", meta.artist.clone()),
+        ("composer", meta.composer.clone()),
+        ("copyright", meta.copyright.clone()),
+        ("key", meta.key.clone()),
+        ("tempo", meta.tempo.map(|tempo| tempo.to_string())),
+        (
+            "duration",
+            meta.duration
+                .map(|duration| format!("{}:{:02}", duration / 60, duration % 60)),
+        ),
+        (
+            "x_arrangement",
+            (!lyric.arrangement.is_empty()).then(|| lyric.arrangement.join(", ")),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|value| format!("{{{name}: {value}}}")))
+    .collect::<Vec<_>>()
+    .join("\n");
+    let parts = lyric.to_parts();
+    let parts = parts.written().iter().map(|part| {
+        let text = Part {
+            label: None,
+            ..part.clone()
+        }
+        .to_text();
+        match &part.label {
+            Some(label) => {
+                let section = Section::from_label(label).name();
+                format!("{{start_of_{section}: {label}}}\n{text}\n{{end_of_{section}}}")
+            }
+            None => text,
+        }
+    });
+    once(directives)
+        .chain(parts)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// The songs of a playlist in one file
+pub fn to_songbook<'a>(lyrics: impl IntoIterator<Item = &'a Lyric>) -> String {
+    lyrics
+        .into_iter()
+        .map(to_chordpro)
+        .collect::<Vec<_>>()
+        .join(&format!("\n\n{NEW_SONG}\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_chordpro, to_chordpro, to_songbook};
+
+    const SONGS: &str = "# Drs. P
+{title: Sofietje}
+{artist: Drs. P}
+{key: G}
+{duration: 2:05}
+
+[G]Zij dronk [D]ranja
+met een rietje
+
+{soc}
+[C]Sofie[G]tje
+
+[C]Sofie[D]tje
+{eoc}
+
+{new_song}
+{t: Dodenrit}
+Over de heide";
+
+    #[test]
+    fn import() {
+        let lyrics = from_chordpro(SONGS);
+        assert_eq!(lyrics.len(), 2);
+        let sofietje = &lyrics[0];
+        assert_eq!(sofietje.title, "Sofietje");
+        assert_eq!(sofietje.meta.artist.as_deref(), Some("Drs. P"));
+        assert_eq!(sofietje.meta.key.as_deref(), Some("G"));
+        assert_eq!(sofietje.meta.duration, Some(125));
+        assert_eq!(
+            sofietje.parts,
+            vec![
+                vec!["Zij dronk ranja".to_owned(), "met een rietje".to_owned()],
+                vec!["Sofietje".to_owned(), "Sofietje".to_owned()]
+            ]
+        );
+        assert_eq!(sofietje.labels, vec![None, Some("Chorus".to_owned())]);
+        assert!(sofietje.chords.is_some());
+        assert_eq!(lyrics[1].title, "Dodenrit");
+        assert_eq!(lyrics[1].parts, vec![vec!["Over de heide".to_owned()]]);
+    }
+
+    #[test]
+    fn export() {
+        let lyrics = from_chordpro(SONGS);
+        let text = to_chordpro(&lyrics[0]);
+        assert_eq!(
+            text,
+            "{title: Sofietje}
+{artist: Drs. P}
+{key: G}
+{duration: 2:05}
+
+[G]Zij dronk [D]ranja
+met een rietje
+
+{start_of_chorus: Chorus}
+[C]Sofie[G]tje
+[C]Sofie[D]tje
+{end_of_chorus}"
+        );
+        let again = from_chordpro(&to_songbook(&lyrics));
+        assert_eq!(again.len(), 2);
+        assert_eq!(again[0].parts, lyrics[0].parts);
+        assert_eq!(again[0].chords, lyrics[0].chords);
+        assert_eq!(again[1].title, lyrics[1].title);
+    }
+}
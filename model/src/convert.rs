@@ -1,142 +1,167 @@
-use crate::{Error, Lyric, LyricId, Playlist, Result, User, Uuid, error::ErrInto, parts::Parts};
+use crate::{Error, Lyric, LyricMeta, Result, Uuid, error::ErrInto, member::Member, parts::Parts};
 use chrono::{DateTime, Utc};
+use spin_sdk::sqlite::Value;
+use spin_sqlite_connection::{FromRow, FromValue, Row, ToSql};
 
-pub trait RowExt {
-    fn column(&self, column_index: usize, column_name: &str) -> Result<String>;
+impl FromValue for Uuid {
+    fn from_value(value: &Value) -> Option<Self> {
+        <&str>::try_from(value).ok()?.parse().ok()
+    }
 }
 
-impl RowExt for spin_sdk::sqlite::RowResult {
-    fn column(&self, column_index: usize, column_name: &str) -> Result<String> {
-        self.get::<&str>(column_index)
-            .map(String::from)
-            .ok_or(Error::Column(format!(
-                "Problem converting value in column {column_name}"
-            )))
+impl ToSql for Uuid {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.to_string())
     }
 }
 
-fn to_datetime(s: String) -> Result<DateTime<Utc>> {
-    s.parse::<DateTime<Utc>>().err_into()
+fn to_tags(s: String) -> Result<Vec<String>> {
+    serde_json::from_str(&s).err_into()
 }
 
-fn to_uuid(s: String) -> Result<Uuid> {
-    s.parse::<Uuid>().err_into()
+pub(crate) fn to_members(s: String) -> Result<Vec<Member>> {
+    serde_json::from_str(&s).err_into()
 }
 
-fn to_parts(s: String) -> Result<Vec<Vec<String>>> {
-    s.parse::<Parts>().err_into().map(|p| p.parts())
+fn to_parts(s: String) -> Result<Parts> {
+    s.parse::<Parts>().err_into()
 }
 
-impl TryFrom<spin_sdk::sqlite::RowResult> for Lyric {
-    type Error = Error;
-
-    fn try_from(row: spin_sdk::sqlite::RowResult) -> Result<Self> {
-        Ok(Self {
-            id: row.column(0, "id")?,
-            title: row.column(1, "title")?,
-            parts: row.column(2, "parts").and_then(to_parts)?,
-            created: row
-                .column(3, "created")
-                .and_then(to_datetime)
-                .map(Into::into)?,
-            modified: row
-                .column(4, "modified")
-                .and_then(to_datetime)
-                .map(Into::into)?,
-            etag: row.column(5, "etag").and_then(to_uuid).map(Into::into)?,
-        })
-    }
+/// The columns of a lyric, the parts column holds the labels, chords and arrangement as well
+#[derive(FromRow)]
+#[row(error = "Error")]
+struct LyricRow {
+    id: String,
+    title: String,
+    #[row(with = "to_parts")]
+    parts: Parts,
+    #[row(flatten)]
+    meta: LyricMeta,
+    #[row(with = "to_tags")]
+    tags: Vec<String>,
+    created: Option<DateTime<Utc>>,
+    modified: Option<DateTime<Utc>>,
+    etag: Option<Uuid>,
 }
 
-impl TryFrom<spin_sdk::sqlite::RowResult> for Playlist {
+impl FromRow for Lyric {
     type Error = Error;
 
-    fn try_from(row: spin_sdk::sqlite::RowResult) -> Result<Self> {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        let row = LyricRow::from_row(row)?;
         Ok(Self {
-            id: row.column(0, "id")?,
-            title: row.column(1, "title")?,
-            members: vec![],
-            created: row
-                .column(2, "created")
-                .and_then(to_datetime)
-                .map(Into::into)?,
-            modified: row
-                .column(3, "created")
-                .and_then(to_datetime)
-                .map(Into::into)?,
-            etag: row.column(4, "etag").and_then(to_uuid).map(Into::into)?,
-        })
+            meta: row.meta,
+            tags: row.tags,
+            created: row.created,
+            modified: row.modified,
+            etag: row.etag,
+            ..Lyric::new(row.id, row.title, vec![])
+        }
+        .with_parts(row.parts))
     }
 }
 
-impl TryFrom<spin_sdk::sqlite::RowResult> for LyricId {
-    type Error = Error;
+#[cfg(test)]
+mod tests {
+    use spin_sdk::sqlite::{RowResult, Value};
+    use spin_sqlite_connection::{FromRow, Row};
 
-    fn try_from(row: spin_sdk::sqlite::RowResult) -> Result<Self> {
-        row.column(0, "id").map(LyricId)
-    }
-}
+    use crate::{Lyric, LyricId, Playlist, Tag, User, error::Error};
 
-impl TryFrom<spin_sdk::sqlite::RowResult> for User {
-    type Error = Error;
-
-    fn try_from(row: spin_sdk::sqlite::RowResult) -> Result<Self> {
-        Ok(Self {
-            id: row.column(0, "id")?,
-            name: row.column(1, "name")?,
-            password: row.column(2, "password")?,
-        })
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    // use spin_sdk::sqlite::{QueryResult, RowResult, Value};
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_owned())
+    }
 
-    // use crate::Lyric;
+    #[test]
+    fn lyric_from_row() {
+        let columns = columns(&[
+            "tags",
+            "etag",
+            "id",
+            "title",
+            "parts",
+            "created",
+            "modified",
+            "artist",
+            "composer",
+            "copyright",
+            "key",
+            "tempo",
+            "language",
+            "duration",
+            "notes",
+        ]);
+        let row = RowResult {
+            values: vec![
+                text(r#"["kerst"]"#),
+                text("U5jCFGBECj34LSqvZKRz92"),
+                text("PKc2FHaQoVbJfjsPHwbUX4"),
+                text("Sofietje"),
+                text("{label: Chorus}\n[G]Zij dronk ranja"),
+                text("2024-05-11T06:38:11.759Z"),
+                text("2024-05-12T06:38:11.759Z"),
+                text("Johnny Meijer"),
+                Value::Null,
+                Value::Null,
+                text("G"),
+                Value::Integer(96),
+                Value::Null,
+                Value::Null,
+                Value::Null,
+            ],
+        };
+        let lyric = Lyric::from_row(&Row::new(&columns, &row)).unwrap();
+        assert_eq!(lyric.title, "Sofietje");
+        assert_eq!(lyric.parts, vec![vec!["Zij dronk ranja".to_owned()]]);
+        assert_eq!(lyric.labels, vec![Some("Chorus".to_owned())]);
+        assert_eq!(lyric.meta.artist.as_deref(), Some("Johnny Meijer"));
+        assert_eq!(lyric.meta.tempo, Some(96));
+        assert_eq!(lyric.meta.composer, None);
+        assert_eq!(lyric.tags, vec!["kerst".to_owned()]);
+        assert_ne!(lyric.created, lyric.modified);
+        assert_eq!(
+            lyric.etag.map(|etag| etag.to_string()).as_deref(),
+            Some("U5jCFGBECj34LSqvZKRz92")
+        );
+    }
 
     #[test]
-    fn list_from_query_result() {
-        // let query_result = QueryResult {
-        //     columns: vec![
-        //         "id".to_owned(),
-        //         "title".to_owned(),
-        //         "parts".to_owned(),
-        //         "created".to_owned(),
-        //         "modified".to_owned(),
-        //         "etag".to_owned(),
-        //     ],
-        //     rows: vec![
-        //         RowResult {
-        //             values: vec![
-        //                 Value::Text("PKc2FHaQoVbJfjsPHwbUX4".to_owned()),
-        //                 Value::Text("Hallo allemaal".to_owned()),
-        //                 Value::Text("Hallo allemaal\nWat fijn dat u bent".to_owned()),
-        //                 Value::Text("2024-05-11T06:38:11.759Z".to_owned()),
-        //                 Value::Text("2024-05-11T06:38:11.759Z".to_owned()),
-        //                 Value::Text("U5jCFGBECj34LSqvZKRz92".to_owned()),
-        //             ],
-        //         },
-        //         RowResult {
-        //             values: vec![
-        //                 Value::Text("B3aC2EHKXDGZcjNvvg4Rs5".to_owned()),
-        //                 Value::Text("Sofietje".to_owned()),
-        //                 Value::Text("Zij dronk ranja met een rietje, mijn sofietje\nOp een amsterdams terras".to_owned()),
-        //                 Value::Text("2024-05-11T06:39:11.759Z".to_owned()),
-        //                 Value::Text("2024-05-11T06:39:11.759Z".to_owned()),
-        //                 Value::Text("UBBrNrdTUXT9nMjBrt5dGu".to_owned()),
-        //             ],
-        //         },
-        //     ],
-        // };
-        // let list = query_result
-        //     .rows()
-        //     .map(Lyric::try_from)
-        //     .collect::<Result<Vec<_>, crate::error::Error>>()
-        //     .unwrap();
+    fn playlist_from_row() {
+        let columns = columns(&["id", "title", "created", "modified", "etag", "members"]);
+        let row = RowResult {
+            values: vec![
+                text("p"),
+                text("Kerst"),
+                text("2024-05-11T06:38:11.759Z"),
+                text("2024-05-12T06:38:11.759Z"),
+                text("UBBrNrdTUXT9nMjBrt5dGu"),
+                text(r#"[{"lyric_id":"a","key":null,"repeat":2,"notes":null,"segment":null}]"#),
+            ],
+        };
+        let playlist = Playlist::from_row(&Row::new(&columns, &row)).unwrap();
+        assert_eq!(playlist.members.len(), 1);
+        assert_eq!(playlist.members[0].details.repeat, Some(2));
+        assert_eq!(playlist.modified, "2024-05-12T06:38:11.759Z".parse().ok());
+    }
 
-        // for lyric in list {
-        //     println!("{}", lyric.title);
-        // }
+    #[test]
+    fn other_rows() {
+        let row = RowResult {
+            values: vec![text("a"), text("kerst"), Value::Integer(3)],
+        };
+        let tag = Tag::from_row(&Row::new(&columns(&["id", "name", "count"]), &row)).unwrap();
+        assert_eq!(tag.count, 3);
+        let user = User::from_row(&Row::new(&columns(&["id", "name", "password"]), &row));
+        assert!(matches!(user, Err(Error::Column(_))));
+        let id = LyricId::from_row(&Row::new(&columns(&["id"]), &row)).unwrap();
+        assert_eq!(id.0, "a");
+        assert!(matches!(
+            LyricId::from_row(&Row::new(&columns(&["value"]), &row)),
+            Err(Error::Column(_))
+        ));
     }
 }
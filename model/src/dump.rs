@@ -0,0 +1,149 @@
+//! The whole database written out piece by piece while it is read,
+//! so the lyrics and playlists never have to be in memory all at once.
+//!
+//! Json gives the same document as [`Db`](crate::Db), ndjson gives one [`Entry`] per line.
+
+use futures::{Stream, StreamExt, TryStreamExt, future::ready, stream};
+use serde::{Deserialize, Serialize};
+
+use crate::{Lyric, Playlist, Result};
+
+pub const APPLICATION_NDJSON: &str = "application/x-ndjson";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Entry {
+    Lyric(Box<Lyric>),
+    Playlist(Playlist),
+}
+
+/// Whether ndjson is asked for before json in the Accept header
+pub fn wants_ndjson(accept: Option<&str>) -> bool {
+    accept
+        .into_iter()
+        .flat_map(|accept| accept.split(','))
+        .filter_map(|range| range.split(';').next())
+        .map(|media_type| media_type.trim().to_ascii_lowercase())
+        .find(|media_type| media_type == APPLICATION_NDJSON || media_type.contains("json"))
+        .is_some_and(|media_type| media_type == APPLICATION_NDJSON)
+}
+
+fn chunk(bytes: &'static [u8]) -> impl Stream<Item = Result<Vec<u8>>> {
+    stream::once(ready(Ok(bytes.to_vec())))
+}
+
+fn elements<T: Serialize>(
+    items: impl Stream<Item = Result<T>>,
+) -> impl Stream<Item = Result<Vec<u8>>> {
+    items.enumerate().map(|(i, item)| {
+        let mut element = if i == 0 { vec![] } else { vec![b','] };
+        serde_json::to_writer(&mut element, &item?)?;
+        Ok(element)
+    })
+}
+
+pub fn json(
+    lyrics: impl Stream<Item = Result<Lyric>>,
+    playlists: impl Stream<Item = Result<Playlist>>,
+) -> impl Stream<Item = Result<Vec<u8>>> {
+    chunk(br#"{"lyrics":["#)
+        .chain(elements(lyrics))
+        .chain(chunk(br#"],"playlists":["#))
+        .chain(elements(playlists))
+        .chain(chunk(b"]}"))
+}
+
+pub fn ndjson(
+    lyrics: impl Stream<Item = Result<Lyric>>,
+    playlists: impl Stream<Item = Result<Playlist>>,
+) -> impl Stream<Item = Result<Vec<u8>>> {
+    lyrics
+        .map_ok(|lyric| Entry::Lyric(Box::new(lyric)))
+        .chain(playlists.map_ok(Entry::Playlist))
+        .map(|entry| {
+            let mut line = serde_json::to_vec(&entry?)?;
+            line.push(b'\n');
+            Ok(line)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{StreamExt, TryStreamExt, executor::block_on, stream};
+
+    use super::{Entry, json, ndjson, wants_ndjson};
+    use crate::{Db, Lyric, Playlist, error::Error};
+
+    fn db() -> Db {
+        Db {
+            lyrics: vec![
+                Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![]),
+                Lyric::new("b".to_owned(), "Dodenrit".to_owned(), vec![]),
+            ],
+            playlists: vec![Playlist::new(
+                "p".to_owned(),
+                "Kerst".to_owned(),
+                vec!["a".into(), "b".into()],
+            )],
+        }
+    }
+
+    fn bytes(db: &Db, ndjson_output: bool) -> Vec<u8> {
+        let lyrics = stream::iter(db.lyrics.clone()).map(Ok);
+        let playlists = stream::iter(db.playlists.clone()).map(Ok);
+        let chunks = if ndjson_output {
+            ndjson(lyrics, playlists).boxed()
+        } else {
+            json(lyrics, playlists).boxed()
+        };
+        block_on(chunks.try_concat()).unwrap()
+    }
+
+    #[test]
+    fn json_is_a_db() {
+        let db = db();
+        assert_eq!(
+            serde_json::from_slice::<Db>(&bytes(&db, false)).unwrap(),
+            db
+        );
+        assert_eq!(
+            bytes(&Db::default(), false),
+            br#"{"lyrics":[],"playlists":[]}"#
+        );
+    }
+
+    #[test]
+    fn ndjson_has_an_entry_per_line() {
+        let db = db();
+        let bytes = bytes(&db, true);
+        let entries = String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Entry>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                Entry::Lyric(Box::new(db.lyrics[0].clone())),
+                Entry::Lyric(Box::new(db.lyrics[1].clone())),
+                Entry::Playlist(db.playlists[0].clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_is_passed_on() {
+        let lyrics = stream::iter(vec![Ok(db().lyrics[0].clone()), Err(Error::Body)]);
+        let chunks = block_on(json(lyrics, stream::empty()).collect::<Vec<_>>());
+        assert!(matches!(chunks[2], Err(Error::Body)));
+    }
+
+    #[test]
+    fn accept() {
+        assert!(wants_ndjson(Some("application/x-ndjson")));
+        assert!(!wants_ndjson(Some(
+            "application/json, application/x-ndjson"
+        )));
+        assert!(!wants_ndjson(None));
+    }
+}
@@ -2,6 +2,8 @@ use axum_core::{body::Body, response::IntoResponse};
 use spin_sdk::http::StatusCode;
 use std::{num::ParseIntError, str::Utf8Error};
 
+use crate::validation::Violation;
+
 #[derive(Debug, thiserror::Error)]
 pub enum AuthenticationError {
     #[error("Username")]
@@ -35,11 +37,27 @@ pub enum Error {
     #[error("Invalid body")]
     Body,
 
+    #[error("Precondition failed")]
+    PreconditionFailed,
+
+    #[error("Unsupported media type")]
+    UnsupportedMediaType,
+
+    #[error("Json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Validation failed for {}", .0.iter().map(|v| v.field.as_str()).collect::<Vec<_>>().join(", "))]
+    Validation(Vec<Violation>),
+
+    #[error("Zip: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
     #[error("Utf 8 encoding")]
     Utf8(#[from] Utf8Error),
 
-    #[error("Column {0}")]
-    Column(String),
+    #[cfg(feature = "response")]
+    #[error("Column: {0}")]
+    Column(#[from] spin_sqlite_connection::ColumnError),
 
     #[error("Chrono: {0}")]
     Chrono(#[from] chrono::ParseError),
@@ -72,6 +90,22 @@ pub enum Error {
     #[cfg(feature = "response")]
     #[error("Spin SQLite: {0}")]
     SpinSQLite(#[from] spin_sdk::sqlite::Error),
+
+    #[cfg(feature = "response")]
+    #[error("Spin key-value: {0}")]
+    SpinKeyValue(#[from] spin_sdk::key_value::Error),
+}
+
+impl Error {
+    /// Malformed json is a bad request, well formed json that does not fit is reported as violation
+    pub fn from_body(error: serde_json::Error) -> Self {
+        match error.classify() {
+            serde_json::error::Category::Data => {
+                Error::Validation(vec![Violation::new("body", error.to_string())])
+            }
+            _ => Error::Body,
+        }
+    }
 }
 
 impl IntoResponse for Error {
@@ -82,6 +116,15 @@ impl IntoResponse for Error {
                 .header("WWW-Authenticate", "Basic realm=\"Lipl Api\"")
                 .body(Body::empty())
                 .unwrap(),
+            Error::NotFound => StatusCode::NOT_FOUND.into_response(),
+            Error::Body => StatusCode::BAD_REQUEST.into_response(),
+            Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED.into_response(),
+            Error::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+            Error::Validation(violations) => axum_core::response::Response::builder()
+                .status(StatusCode::UNPROCESSABLE_ENTITY)
+                .header("Content-Type", "application/json")
+                .body(Body::from(violations_json(&violations)))
+                .unwrap(),
             Error::Base58Decode(e) => {
                 println!("{}", e);
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -90,6 +133,7 @@ impl IntoResponse for Error {
                 println!("{}", e);
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
+            #[cfg(feature = "response")]
             Error::Column(e) => {
                 println!("{}", e);
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -102,6 +146,11 @@ impl IntoResponse for Error {
                 println!("{}", e);
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
             }
+            #[cfg(feature = "response")]
+            Error::SpinKeyValue(e) => {
+                println!("{}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
             _ => {
                 println!("Unknown error");
                 StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -110,6 +159,15 @@ impl IntoResponse for Error {
     }
 }
 
+#[derive(serde::Serialize)]
+struct Violations<'a> {
+    violations: &'a [Violation],
+}
+
+pub fn violations_json(violations: &[Violation]) -> String {
+    serde_json::to_string(&Violations { violations }).unwrap_or_default()
+}
+
 pub trait ErrInto<T, E> {
     fn err_into(self) -> Result<T, Error>;
 }
@@ -5,16 +5,30 @@ use error::ErrInto;
 use serde::{Deserialize, Serialize};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 
-use crate::error::Error;
+use crate::{
+    chord::{Chord, ChordAt, Key},
+    error::Error,
+    member::Member,
+    parts::Parts,
+};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+pub mod archive;
+pub mod chord;
+pub mod chordpro;
 #[cfg(feature = "response")]
 pub mod convert;
+pub mod dump;
 pub mod error;
+pub mod member;
+pub mod openlyrics;
 pub mod parts;
+pub mod patch;
 #[cfg(feature = "response")]
 pub mod response;
+pub mod songbook;
+pub mod validation;
 
 pub trait Etag {
     fn etag(&self) -> String;
@@ -25,6 +39,20 @@ pub struct Lyric {
     pub id: String,
     pub title: String,
     pub parts: Vec<Vec<String>>,
+    /// Label of the part at the same position, like `Chorus`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<Option<String>>,
+    /// Part labels in the order they are sung
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arrangement: Vec<String>,
+    /// Chords of every line of every part, only part of the representation with `?with=chords`.
+    /// Left out on update, the chords of unchanged lines are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chords: Option<Vec<Vec<Vec<ChordAt>>>>,
+    #[serde(flatten)]
+    pub meta: LyricMeta,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     #[serde(skip)]
     pub created: Option<DateTime<Utc>>,
     #[serde(skip)]
@@ -34,11 +62,156 @@ pub struct Lyric {
 }
 
 impl Lyric {
+    /// The parts with their labels, chords and the arrangement
+    pub fn to_parts(&self) -> Parts {
+        Parts::labeled(
+            self.parts.clone(),
+            self.labels.clone(),
+            self.arrangement.clone(),
+        )
+        .with_chords(self.chords.clone().unwrap_or_default())
+    }
+
+    /// Takes over the parts, the `{key:}` directive is used when the key is not known yet
+    pub fn with_parts(self, parts: Parts) -> Self {
+        let chords = parts.chords();
+        Self {
+            parts: parts.parts(),
+            labels: parts.labels(),
+            arrangement: parts.arrangement().to_vec(),
+            chords: (!chords.is_empty()).then_some(chords),
+            meta: LyricMeta {
+                key: self.meta.key.or(parts.key().map(String::from)),
+                ..self.meta
+            },
+            ..self
+        }
+    }
+
+    /// Transposes the chords and the key, spelled with sharps or flats like the new key.
+    /// Without a key the first chord is taken as key, without chords there is nothing to transpose.
+    pub fn transposed(self, transpose: Transpose) -> Self {
+        let chords = self.chords.iter().flatten().flatten().flatten();
+        let source = self
+            .meta
+            .key
+            .as_deref()
+            .and_then(|key| key.parse::<Key>().ok())
+            .or_else(|| {
+                chords
+                    .filter_map(|chord| chord.chord.parse::<Chord>().ok())
+                    .map(|chord| Key::from(&chord))
+                    .next()
+            });
+        let (semitones, target) = match (transpose, source) {
+            (Transpose::By(semitones), source) => {
+                (semitones, source.map(|key| key.transpose(semitones)))
+            }
+            (Transpose::To(target), Some(source)) => (source.semitones_to(&target), Some(target)),
+            (Transpose::To(_), None) => return self,
+        };
+        let flats = target.map(|key| key.flats()).unwrap_or(semitones < 0);
+        let chords = self.chords.map(|chords| {
+            chords
+                .into_iter()
+                .map(|part| {
+                    part.into_iter()
+                        .map(|line| {
+                            line.into_iter()
+                                .map(|at| ChordAt {
+                                    chord: at
+                                        .chord
+                                        .parse::<Chord>()
+                                        .map(|chord| chord.transpose(semitones, flats).to_string())
+                                        .unwrap_or(at.chord),
+                                    ..at
+                                })
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        Self {
+            chords,
+            meta: LyricMeta {
+                key: self
+                    .meta
+                    .key
+                    .as_ref()
+                    .and(target)
+                    .map(|key| key.to_string())
+                    .or(self.meta.key),
+                ..self.meta
+            },
+            ..self
+        }
+    }
+
+    /// Without chords, the chords of the stored lyric are kept for the lines that did not change
+    pub fn keep_chords(self, stored: &Lyric) -> Self {
+        match (&self.chords, &stored.chords) {
+            (None, Some(chords)) => {
+                let kept = self
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, part)| {
+                        part.iter()
+                            .enumerate()
+                            .map(|(j, line)| {
+                                stored
+                                    .parts
+                                    .get(i)
+                                    .and_then(|part| part.get(j))
+                                    .filter(|stored_line| *stored_line == line)
+                                    .and_then(|_| chords.get(i).and_then(|part| part.get(j)))
+                                    .cloned()
+                                    .unwrap_or_default()
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                let any = kept.iter().flatten().any(|chords| !chords.is_empty());
+                Self {
+                    chords: any.then_some(kept),
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    /// Takes over the meta data and tags of the stored lyric, except a key from the `{key:}` directive of the text
+    pub fn keep_meta_and_tags(self, stored: &Lyric) -> Self {
+        Self {
+            meta: LyricMeta {
+                key: self.meta.key.or_else(|| stored.meta.key.clone()),
+                ..stored.meta.clone()
+            },
+            tags: stored.tags.clone(),
+            ..self
+        }
+    }
+
+    /// Plain text with the title on the first line, followed by the parts
+    pub fn from_text(id: String, text: &str) -> Result<Self> {
+        let text = text.trim_start();
+        let (title, parts) = text.split_once('\n').unwrap_or((text, ""));
+        let parts = parts.parse::<Parts>().map_err(|_| Error::Body)?;
+        Ok(Self::new(id, title.trim().to_owned(), vec![]).with_parts(parts))
+    }
+
     pub fn new(id: String, title: String, parts: Vec<Vec<String>>) -> Self {
         Self {
             id,
             title,
             parts,
+            labels: vec![],
+            arrangement: vec![],
+            chords: None,
+            meta: LyricMeta::default(),
+            tags: vec![],
             created: None,
             modified: None,
             etag: None,
@@ -46,6 +219,148 @@ impl Lyric {
     }
 }
 
+/// Optional information about a lyric, absent fields are left out of the json representation
+#[derive(Clone, Debug, Default, Deserialize, Hash, Serialize, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "response",
+    derive(spin_sqlite_connection::FromRow),
+    row(error = "Error")
+)]
+pub struct LyricMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub composer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+    /// Original key, for instance `G` or `Am`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Beats per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Duration in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+/// Filter for the lyric list, matching is case insensitive.
+/// Only lyrics with all the given tags are selected.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct LyricQuery {
+    pub artist: Option<String>,
+    pub composer: Option<String>,
+    pub key: Option<String>,
+    pub language: Option<String>,
+    #[serde(default, rename = "tag")]
+    pub tags: Vec<String>,
+    /// Optional parts of the representation, like `with=chords`
+    #[serde(default)]
+    pub with: Vec<Include>,
+    /// Number of semitones to transpose the chords, like `+2` or `-3`
+    pub transpose: Option<String>,
+    /// Key to transpose the chords to, the `key` of the query string when a single lyric is asked for
+    #[serde(skip)]
+    pub to_key: Option<String>,
+}
+
+/// Transposition of the chords of a lyric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transpose {
+    By(i32),
+    To(Key),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Include {
+    Chords,
+}
+
+impl LyricQuery {
+    /// Parses a query string like `tag=christmas&tag=dutch&language=nl`
+    pub fn from_query_string(query: &str) -> Result<Self> {
+        let query = serde_html_form::from_str::<Self>(query)
+            .map_err(|_| Error::Body)
+            .map(|query| Self {
+                tags: query.tags.iter().map(|tag| Tag::normalize(tag)).collect(),
+                ..query
+            })?;
+        query.transposition().map(|_| query)
+    }
+
+    /// Parses the query string of a single lyric, where `key=A` is the key to transpose the chords to
+    pub fn for_lyric(query: &str) -> Result<Self> {
+        let query = Self::from_query_string(query)?;
+        let query = Self {
+            to_key: query.key.clone(),
+            ..query
+        };
+        query.transposition().map(|_| query)
+    }
+
+    /// The target key wins from the number of semitones when both are given.
+    /// A plus sign in the query string is decoded as a space, so `transpose=+2` arrives as ` 2`.
+    pub fn transposition(&self) -> Result<Option<Transpose>> {
+        match (&self.to_key, &self.transpose) {
+            (Some(key), _) => key.parse::<Key>().map(Transpose::To).map(Some),
+            (None, Some(semitones)) => semitones
+                .trim()
+                .trim_start_matches('+')
+                .parse::<i32>()
+                .map(|semitones| Some(Transpose::By(semitones)))
+                .map_err(|_| Error::Body),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Transposes when asked for and leaves out what is not asked for
+    pub fn apply(&self, lyric: Lyric) -> Lyric {
+        let lyric = match self.transposition() {
+            Ok(Some(transpose)) => lyric.transposed(transpose),
+            _ => lyric,
+        };
+        if self.with.contains(&Include::Chords) {
+            lyric
+        } else {
+            Lyric {
+                chords: None,
+                ..lyric
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Hash, Serialize, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "response",
+    derive(spin_sqlite_connection::FromRow),
+    row(error = "Error")
+)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    /// Number of lyrics with this tag
+    #[serde(default, skip_deserializing)]
+    pub count: u32,
+}
+
+impl Tag {
+    /// Tag names are stored trimmed and in lowercase
+    pub fn normalize(name: &str) -> String {
+        name.trim().to_lowercase()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TagPost {
+    pub name: String,
+}
+
 pub struct List<T> {
     pub inner: Vec<T>,
 }
@@ -54,13 +369,42 @@ pub struct List<T> {
 pub struct LyricPost {
     pub title: String,
     pub parts: Vec<Vec<String>>,
+    #[serde(default)]
+    pub labels: Vec<Option<String>>,
+    #[serde(default)]
+    pub arrangement: Vec<String>,
+    #[serde(default)]
+    pub chords: Option<Vec<Vec<Vec<ChordAt>>>>,
+    #[serde(flatten)]
+    pub meta: LyricMeta,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl LyricPost {
+    pub fn into_lyric(self, id: String) -> Lyric {
+        Lyric {
+            labels: self.labels,
+            arrangement: self.arrangement,
+            chords: self.chords,
+            meta: self.meta,
+            tags: self.tags,
+            ..Lyric::new(id, self.title, self.parts)
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Hash, Serialize, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "response",
+    derive(spin_sqlite_connection::FromRow),
+    row(error = "Error")
+)]
 pub struct Playlist {
     pub id: String,
     pub title: String,
-    pub members: Vec<String>,
+    #[cfg_attr(feature = "response", row(with = "convert::to_members"))]
+    pub members: Vec<Member>,
     #[serde(skip)]
     pub created: Option<chrono::DateTime<Utc>>,
     #[serde(skip)]
@@ -70,7 +414,7 @@ pub struct Playlist {
 }
 
 impl Playlist {
-    pub fn new(id: String, title: String, members: Vec<String>) -> Self {
+    pub fn new(id: String, title: String, members: Vec<Member>) -> Self {
         Self {
             id,
             title,
@@ -82,15 +426,74 @@ impl Playlist {
     }
 }
 
-pub struct LyricId(pub String);
+#[cfg_attr(
+    feature = "response",
+    derive(spin_sqlite_connection::FromRow),
+    row(error = "Error")
+)]
+pub struct LyricId(#[cfg_attr(feature = "response", row(column = "id"))] pub String);
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct PlaylistPost {
     pub title: String,
-    pub members: Vec<String>,
+    pub members: Vec<Member>,
+}
+
+/// Adds a lyric to a playlist, appended when no zero based position is given.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MemberPost {
+    #[serde(flatten)]
+    pub member: Member,
+    #[serde(default)]
+    pub position: Option<usize>,
+}
+
+/// Moves the playlist member at zero based position `from` to position `to`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MemberMove {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Representation of playlist members in responses
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberFormat {
+    /// Lyric id for members without details, an object otherwise
+    #[default]
+    Entries,
+    /// Lyric id only, for clients that do not know about member details
+    Ids,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct PlaylistQuery {
+    #[serde(default)]
+    pub members: MemberFormat,
+}
+
+impl PlaylistQuery {
+    pub fn apply(&self, playlist: Playlist) -> Playlist {
+        match self.members {
+            MemberFormat::Entries => playlist,
+            MemberFormat::Ids => Playlist {
+                members: playlist
+                    .members
+                    .into_iter()
+                    .map(Member::without_details)
+                    .collect(),
+                ..playlist
+            },
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Hash, Serialize, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "response",
+    derive(spin_sqlite_connection::FromRow),
+    row(error = "Error")
+)]
 pub struct User {
     pub id: String,
     pub name: String,
@@ -98,6 +501,25 @@ pub struct User {
     pub password: String,
 }
 
+/// A choir with its own database, selected by host name or by the `/lipl/t/{name}` path prefix
+#[derive(Clone, Debug, Deserialize, Hash, Serialize, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "response",
+    derive(spin_sqlite_connection::FromRow),
+    row(error = "Error")
+)]
+pub struct Tenant {
+    /// Label of the database, also used in the path prefix
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// The basic credentials of the api of the tenant, instead of `lipl_username` and `lipl_password`
+    #[serde(default)]
+    pub username: String,
+    #[serde(default, skip_serializing)]
+    pub password: String,
+}
+
 impl std::fmt::Display for User {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}: {}", self.id, self.name)
@@ -159,6 +581,144 @@ mod test {
         assert_eq!(y, Some(8));
     }
 
+    #[test]
+    fn lyric_without_meta() {
+        let lyric: super::Lyric =
+            serde_json::from_str(r#"{"id":"a","title":"Sofietje","parts":[["Zij dronk ranja"]]}"#)
+                .unwrap();
+        assert_eq!(lyric.meta, super::LyricMeta::default());
+        assert_eq!(
+            serde_json::to_string(&lyric).unwrap(),
+            r#"{"id":"a","title":"Sofietje","parts":[["Zij dronk ranja"]]}"#
+        );
+    }
+
+    #[test]
+    fn lyric_with_meta() {
+        let json =
+            r#"{"id":"a","title":"Sofietje","parts":[],"artist":"Drs. P","key":"G","tempo":96}"#;
+        let lyric: super::Lyric = serde_json::from_str(json).unwrap();
+        assert_eq!(lyric.meta.artist.as_deref(), Some("Drs. P"));
+        assert_eq!(lyric.meta.tempo, Some(96));
+        assert_eq!(serde_json::to_string(&lyric).unwrap(), json);
+    }
+
+    #[test]
+    fn lyric_query() {
+        let query =
+            super::LyricQuery::from_query_string("tag=Christmas&tag=dutch&language=nl").unwrap();
+        assert_eq!(query.tags, vec!["christmas".to_owned(), "dutch".to_owned()]);
+        assert_eq!(query.language.as_deref(), Some("nl"));
+        assert_eq!(
+            super::LyricQuery::from_query_string("").unwrap(),
+            super::LyricQuery::default()
+        );
+    }
+
+    #[test]
+    fn chords_only_with_chords() {
+        let lyric = super::Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![])
+            .with_parts("[G]Zij dronk [D]ranja".parse().unwrap());
+        assert_eq!(lyric.parts, vec![vec!["Zij dronk ranja".to_owned()]]);
+        let with = super::LyricQuery::from_query_string("with=chords").unwrap();
+        assert!(with.apply(lyric.clone()).chords.is_some());
+        assert!(
+            super::LyricQuery::default()
+                .apply(lyric.clone())
+                .chords
+                .is_none()
+        );
+
+        let update = super::Lyric::new(
+            "a".to_owned(),
+            "Sofietje".to_owned(),
+            vec![vec![
+                "Zij dronk ranja".to_owned(),
+                "met een rietje".to_owned(),
+            ]],
+        )
+        .keep_chords(&lyric);
+        assert_eq!(
+            update.to_parts().to_text(),
+            "[G]Zij dronk [D]ranja\nmet een rietje"
+        );
+    }
+
+    #[test]
+    fn transposed() {
+        let lyric = super::Lyric {
+            meta: super::LyricMeta {
+                key: Some("G".to_owned()),
+                ..Default::default()
+            },
+            ..super::Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![])
+        }
+        .with_parts(
+            "[G]Zij dronk [D/F#]ranja met een [Em7]rietje"
+                .parse()
+                .unwrap(),
+        );
+        let text = |query: &str| {
+            let lyric = super::LyricQuery::for_lyric(query)
+                .unwrap()
+                .apply(lyric.clone());
+            (lyric.meta.key.clone().unwrap(), lyric.to_parts().to_text())
+        };
+        assert_eq!(
+            text("with=chords&transpose=+2"),
+            (
+                "A".to_owned(),
+                "[A]Zij dronk [E/G#]ranja met een [F#m7]rietje".to_owned()
+            )
+        );
+        assert_eq!(
+            text("with=chords&key=F&transpose=3"),
+            (
+                "F".to_owned(),
+                "[F]Zij dronk [C/E]ranja met een [Dm7]rietje".to_owned()
+            )
+        );
+        assert_eq!(
+            text("with=chords&transpose=-4"),
+            (
+                "Eb".to_owned(),
+                "[Eb]Zij dronk [Bb/D]ranja met een [Cm7]rietje".to_owned()
+            )
+        );
+        assert_eq!(text("transpose=1").0, "Ab");
+        assert!(super::LyricQuery::from_query_string("transpose=two").is_err());
+        assert!(super::LyricQuery::for_lyric("key=H").is_err());
+        for key in ["H", "G major", "Bes"] {
+            let query = super::LyricQuery::from_query_string(&format!("key={key}")).unwrap();
+            assert_eq!(query.key.as_deref(), Some(key));
+            assert_eq!(query.transposition().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn from_text() {
+        let lyric = super::Lyric::from_text(
+            "a".to_owned(),
+            "\n  Sofietje \r\n\nZij dronk ranja\nmet een rietje\n\n\n{label: Chorus}\nSofietje\n",
+        )
+        .unwrap();
+        assert_eq!(lyric.title, "Sofietje");
+        assert_eq!(
+            lyric.parts,
+            vec![
+                vec!["Zij dronk ranja".to_owned(), "met een rietje".to_owned()],
+                vec!["Sofietje".to_owned()]
+            ]
+        );
+        assert_eq!(lyric.labels, vec![None, Some("Chorus".to_owned())]);
+        assert!(
+            super::Lyric::from_text("b".to_owned(), "Sofietje")
+                .unwrap()
+                .parts
+                .is_empty()
+        );
+    }
+
     #[test]
     fn new() {
         let uuid = super::Uuid::default();
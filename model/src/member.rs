@@ -0,0 +1,121 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// An entry in a playlist. The same lyric can be a member of a playlist more than once.
+///
+/// A member without details is (de)serialized as the plain lyric id, so
+/// existing clients keep receiving an array of ids.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Member {
+    pub lyric_id: String,
+    pub details: Details,
+}
+
+/// Optional per entry information, like the key to play the lyric in.
+#[derive(Clone, Debug, Default, Deserialize, Hash, Serialize, PartialEq, Eq)]
+pub struct Details {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<String>,
+}
+
+impl Details {
+    pub fn is_empty(&self) -> bool {
+        self == &Details::default()
+    }
+}
+
+impl Member {
+    pub fn new(lyric_id: String, details: Details) -> Self {
+        Self { lyric_id, details }
+    }
+
+    pub fn without_details(self) -> Self {
+        self.lyric_id.into()
+    }
+}
+
+impl From<String> for Member {
+    fn from(lyric_id: String) -> Self {
+        Self::new(lyric_id, Details::default())
+    }
+}
+
+impl From<&str> for Member {
+    fn from(lyric_id: &str) -> Self {
+        lyric_id.to_owned().into()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Entry<I, D> {
+    lyric_id: I,
+    #[serde(flatten)]
+    details: D,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MemberRepr {
+    Id(String),
+    Entry(Entry<String, Details>),
+}
+
+impl Serialize for Member {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.details.is_empty() {
+            serializer.serialize_str(&self.lyric_id)
+        } else {
+            Entry {
+                lyric_id: &self.lyric_id,
+                details: &self.details,
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Member {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        MemberRepr::deserialize(deserializer).map(|repr| match repr {
+            MemberRepr::Id(lyric_id) => lyric_id.into(),
+            MemberRepr::Entry(entry) => Self::new(entry.lyric_id, entry.details),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Details, Member};
+
+    #[test]
+    fn plain_id_round_trip() {
+        let members: Vec<Member> = serde_json::from_str(r#"["a", "b", "a"]"#).unwrap();
+        assert_eq!(members, vec!["a".into(), "b".into(), "a".into()]);
+        assert_eq!(serde_json::to_string(&members).unwrap(), r#"["a","b","a"]"#);
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        let json = r#"["a",{"lyric_id":"b","key":"G","repeat":2,"segment":"Encore"}]"#;
+        let members: Vec<Member> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            members[1],
+            Member::new(
+                "b".to_owned(),
+                Details {
+                    key: Some("G".to_owned()),
+                    repeat: Some(2),
+                    notes: None,
+                    segment: Some("Encore".to_owned()),
+                }
+            )
+        );
+        assert_eq!(serde_json::to_string(&members).unwrap(), json);
+        assert_eq!(members[1].clone().without_details(), "b".into());
+    }
+}
@@ -0,0 +1,522 @@
+//! Songs in [OpenLyrics](https://docs.openlyrics.org) 0.9 format, used by OpenLP and other presentation software.
+//!
+//! Part labels like `Verse 1` or `Chorus` map onto verse names like `v1` and `c`,
+//! other labels and unlabeled parts get a generated name. A label that the verse name does not give back
+//! is kept in a `lipl:label` attribute of the verse. The sung text survives a round trip unchanged,
+//! the duration has no place in OpenLyrics and is left out.
+//! More than one song is written as a `<songs>` collection of `<song>` elements.
+
+use quick_xml::{
+    Reader,
+    escape::escape,
+    events::{BytesStart, Event},
+};
+
+use crate::{
+    Lyric, LyricMeta, Result, Uuid,
+    chord::ChordAt,
+    error::Error,
+    parts::{Part, Parts},
+};
+
+pub const CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+pub const EXTENSION: &str = ".xml";
+
+const NAMESPACE: &str = "http://openlyrics.info/namespace/2009/song";
+/// Namespace of the attributes that OpenLyrics has no place for
+const LIPL_NAMESPACE: &str = "https://github.com/paulusminus/lipl-storage-spin";
+const VERSION: &str = "0.9";
+const DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+/// Verse name prefixes and the labels they stand for
+const SECTIONS: [(&str, &str); 7] = [
+    ("v", "Verse"),
+    ("c", "Chorus"),
+    ("p", "Pre-Chorus"),
+    ("b", "Bridge"),
+    ("i", "Intro"),
+    ("e", "Ending"),
+    ("o", "Other"),
+];
+
+/// `Verse 1` becomes `v1` and `Chorus` becomes `c`
+fn verse_name(label: &str) -> Option<String> {
+    SECTIONS.iter().find_map(|(prefix, word)| {
+        let number = label.strip_prefix(word)?;
+        if number.is_empty() {
+            Some(prefix.to_string())
+        } else {
+            let number = number.strip_prefix(' ')?;
+            (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+                .then(|| format!("{prefix}{number}"))
+        }
+    })
+}
+
+/// `v1` becomes `Verse 1`, names with an unknown prefix are used as label
+fn label(name: &str) -> String {
+    let split = name
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(name.len());
+    let (prefix, number) = name.split_at(split);
+    match SECTIONS.iter().find(|(p, _)| *p == prefix) {
+        Some((_, word)) if number.is_empty() => word.to_string(),
+        Some((_, word)) if number.chars().all(|c| c.is_ascii_digit()) => format!("{word} {number}"),
+        _ => name.to_owned(),
+    }
+}
+
+/// Unique verse names for the parts, in the same order
+fn verse_names(parts: &[Part]) -> Vec<String> {
+    let mut used = parts
+        .iter()
+        .filter_map(|part| part.label.as_deref().and_then(verse_name))
+        .collect::<Vec<_>>();
+    let mut taken = vec![];
+    parts
+        .iter()
+        .map(|part| {
+            let name = part
+                .label
+                .as_deref()
+                .and_then(verse_name)
+                .filter(|name| !taken.contains(name))
+                .unwrap_or_else(|| {
+                    let prefix = if part.label.is_some() { "o" } else { "v" };
+                    let name = (1..)
+                        .map(|i| format!("{prefix}{i}"))
+                        .find(|name| !used.contains(name))
+                        .unwrap_or_default();
+                    used.push(name.clone());
+                    name
+                });
+            taken.push(name.clone());
+            name
+        })
+        .collect()
+}
+
+fn line(text: &str, chords: &[ChordAt]) -> String {
+    let mut xml = String::new();
+    let mut chars = text.chars().enumerate().peekable();
+    for chord in chords {
+        let mut segment = String::new();
+        while let Some((_, c)) = chars.next_if(|(i, _)| *i < chord.position) {
+            segment.push(c);
+        }
+        xml.push_str(&escape(segment.as_str()));
+        xml.push_str(&format!(
+            r#"<chord name="{}"/>"#,
+            escape(chord.chord.as_str())
+        ));
+    }
+    xml.push_str(&escape(chars.map(|(_, c)| c).collect::<String>().as_str()));
+    xml
+}
+
+fn element(name: &str, attributes: &str, value: &str) -> String {
+    format!("<{name}{attributes}>{}</{name}>", escape(value))
+}
+
+fn song(lyric: &Lyric) -> String {
+    let meta = &lyric.meta;
+    let parts = lyric.to_parts();
+    let names = verse_names(parts.written());
+    let order = lyric
+        .arrangement
+        .iter()
+        .filter_map(|label| {
+            parts
+                .written()
+                .iter()
+                .position(|part| part.label.as_ref() == Some(label))
+                .map(|i| names[i].as_str())
+        })
+        .collect::<Vec<_>>();
+    let authors = [
+        meta.artist
+            .as_ref()
+            .map(|artist| element("author", "", artist)),
+        meta.composer
+            .as_ref()
+            .map(|composer| element("author", r#" type="music""#, composer)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<String>();
+    let properties = [
+        Some(format!(
+            "<titles>{}</titles>",
+            element("title", "", &lyric.title)
+        )),
+        (!authors.is_empty()).then(|| format!("<authors>{authors}</authors>")),
+        meta.copyright
+            .as_ref()
+            .map(|copyright| element("copyright", "", copyright)),
+        meta.key.as_ref().map(|key| element("key", "", key)),
+        meta.tempo
+            .map(|tempo| element("tempo", r#" type="bpm""#, &tempo.to_string())),
+        (!order.is_empty()).then(|| element("verseOrder", "", &order.join(" "))),
+        (!lyric.tags.is_empty()).then(|| {
+            let themes = lyric
+                .tags
+                .iter()
+                .map(|tag| element("theme", "", tag))
+                .collect::<String>();
+            format!("<themes>{themes}</themes>")
+        }),
+        meta.notes
+            .as_ref()
+            .map(|notes| format!("<comments>{}</comments>", element("comment", "", notes))),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|property| format!("    {property}\n"))
+    .collect::<String>();
+    let lang = meta
+        .language
+        .as_ref()
+        .map(|language| format!(r#" lang="{}""#, escape(language.as_str())))
+        .unwrap_or_default();
+    let verses = parts
+        .written()
+        .iter()
+        .zip(&names)
+        .map(|(part, name)| {
+            let lines = part
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    line(
+                        text,
+                        part.chords.get(i).map(Vec::as_slice).unwrap_or_default(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let lines = if lines.is_empty() {
+                String::new()
+            } else {
+                format!("<lines>{}</lines>", lines.join("<br/>"))
+            };
+            let original = part
+                .label
+                .as_ref()
+                .filter(|original| **original != label(name))
+                .map(|original| format!(r#" lipl:label="{}""#, escape(original.as_str())))
+                .unwrap_or_default();
+            format!("    <verse name=\"{name}\"{lang}{original}>{lines}</verse>\n")
+        })
+        .collect::<String>();
+    let modified = lyric
+        .modified
+        .map(|modified| format!(r#" modifiedDate="{}""#, modified.to_rfc3339()))
+        .unwrap_or_default();
+    format!(
+        "<song xmlns=\"{NAMESPACE}\" xmlns:lipl=\"{LIPL_NAMESPACE}\" version=\"{VERSION}\" createdIn=\"lipl\" modifiedIn=\"lipl\"{modified}>\n  <properties>\n{properties}  </properties>\n  <lyrics>\n{verses}  </lyrics>\n</song>"
+    )
+}
+
+pub fn to_openlyrics(lyric: &Lyric) -> String {
+    format!("{DECLARATION}\n{}\n", song(lyric))
+}
+
+/// Several songs in one document
+pub fn to_collection<'a>(lyrics: impl IntoIterator<Item = &'a Lyric>) -> String {
+    let songs = lyrics.into_iter().map(song).collect::<Vec<_>>().join("\n");
+    format!("{DECLARATION}\n<songs>\n{songs}\n</songs>\n")
+}
+
+#[derive(Default)]
+struct Song {
+    title: Option<String>,
+    meta: LyricMeta,
+    tags: Vec<String>,
+    order: Vec<String>,
+    parts: Vec<Part>,
+    names: Vec<String>,
+    line: Option<(String, Vec<ChordAt>)>,
+    author_type: Option<String>,
+}
+
+impl Song {
+    fn start_verse(
+        &mut self,
+        name: Option<String>,
+        lang: Option<String>,
+        original: Option<String>,
+    ) {
+        let name = name.unwrap_or_else(|| format!("v{}", self.parts.len() + 1));
+        self.parts.push(Part {
+            label: Some(original.unwrap_or_else(|| label(&name))),
+            ..Default::default()
+        });
+        self.names.push(name);
+        if self.meta.language.is_none() {
+            self.meta.language = lang;
+        }
+    }
+
+    fn end_line(&mut self) {
+        if let (Some((text, chords)), Some(part)) = (self.line.take(), self.parts.last_mut()) {
+            part.lines.push(text);
+            part.chords.push(chords);
+        }
+    }
+
+    fn into_lyric(self) -> Lyric {
+        let arrangement = self
+            .order
+            .iter()
+            .filter_map(|name| self.names.iter().position(|n| n == name))
+            .filter_map(|i| self.parts[i].label.clone())
+            .collect();
+        let parts = self
+            .parts
+            .into_iter()
+            .map(|part| {
+                if part.chords.iter().all(Vec::is_empty) {
+                    Part {
+                        chords: vec![],
+                        ..part
+                    }
+                } else {
+                    part
+                }
+            })
+            .collect();
+        Lyric {
+            meta: self.meta,
+            tags: self.tags,
+            ..Lyric::new(
+                Uuid::default().to_string(),
+                self.title.unwrap_or_default(),
+                vec![],
+            )
+            .with_parts(Parts::new(parts, arrangement))
+        }
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == name.as_bytes())
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+fn chord(element: &BytesStart) -> Option<String> {
+    attribute(element, "name").or_else(|| {
+        let root = attribute(element, "root")?;
+        Some(match attribute(element, "bass") {
+            Some(bass) => format!("{root}/{bass}"),
+            None => root,
+        })
+    })
+}
+
+/// Line breaks in the source are layout, only `<br/>` starts a new line
+fn without_layout(text: &str) -> String {
+    let mut lines = text.split('\n');
+    let first = lines.next().unwrap_or_default().trim_end_matches('\r');
+    lines.fold(first.to_owned(), |acc, line| {
+        acc + line.trim_end_matches('\r').trim_start()
+    })
+}
+
+/// All songs in a `<song>` document or a `<songs>` collection, with new ids
+pub fn from_openlyrics(xml: &str) -> Result<Vec<Lyric>> {
+    let mut reader = Reader::from_str(xml);
+    let mut path: Vec<String> = vec![];
+    let mut songs = vec![];
+    let mut song: Option<Song> = None;
+    loop {
+        let event = reader.read_event().map_err(|_| Error::Body)?;
+        let (start, empty) = match &event {
+            Event::Start(element) => (Some(element), false),
+            Event::Empty(element) => (Some(element), true),
+            _ => (None, false),
+        };
+        if let Some(element) = start {
+            let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+            match (name.as_str(), song.as_mut()) {
+                ("song", _) => song = Some(Song::default()),
+                ("author", Some(song)) => song.author_type = attribute(element, "type"),
+                ("verse", Some(song)) => song.start_verse(
+                    attribute(element, "name"),
+                    attribute(element, "lang"),
+                    attribute(element, "label"),
+                ),
+                ("lines", Some(song)) => song.line = Some(Default::default()),
+                ("br", Some(song)) if song.line.is_some() => {
+                    song.end_line();
+                    song.line = Some(Default::default());
+                }
+                ("chord", Some(song)) => {
+                    if let (Some((text, chords)), Some(chord)) = (&mut song.line, chord(element)) {
+                        chords.push(ChordAt {
+                            position: text.chars().count(),
+                            chord,
+                        });
+                    }
+                }
+                _ => {}
+            }
+            if !empty {
+                path.push(name);
+            }
+            continue;
+        }
+        match event {
+            Event::Text(text) => {
+                let Some(song) = song.as_mut() else {
+                    continue;
+                };
+                let text = text.unescape().map_err(|_| Error::Body)?;
+                let in_comment = path.iter().any(|name| name == "comment");
+                match path.last().map(String::as_str) {
+                    _ if song.line.is_some() => {
+                        if let Some((line, _)) = song.line.as_mut().filter(|_| !in_comment) {
+                            line.push_str(&without_layout(&text));
+                        }
+                    }
+                    Some("title") if song.title.is_none() => {
+                        song.title = Some(text.trim().to_owned())
+                    }
+                    Some("author") => {
+                        let value = Some(text.trim().to_owned());
+                        let meta = &mut song.meta;
+                        match song.author_type.as_deref() {
+                            Some("music") => meta.composer = meta.composer.take().or(value),
+                            Some("translation") => {}
+                            _ => meta.artist = meta.artist.take().or(value),
+                        }
+                    }
+                    Some("copyright") => song.meta.copyright = Some(text.trim().to_owned()),
+                    Some("key") => song.meta.key = Some(text.trim().to_owned()),
+                    Some("tempo") => song.meta.tempo = text.trim().parse().ok(),
+                    Some("verseOrder") => {
+                        song.order = text.split_whitespace().map(String::from).collect()
+                    }
+                    Some("theme") => song.tags.push(text.trim().to_owned()),
+                    Some("comment") => {
+                        let notes = song.meta.notes.take();
+                        song.meta.notes = Some(match notes {
+                            Some(notes) => format!("{notes}\n{}", text.trim()),
+                            None => text.trim().to_owned(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(element) => {
+                path.pop();
+                match (element.local_name().as_ref(), song.as_mut()) {
+                    (b"lines", Some(song)) => song.end_line(),
+                    (b"song", Some(_)) => songs.extend(song.take().map(Song::into_lyric)),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(songs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_openlyrics, label, to_collection, to_openlyrics, verse_name};
+    use crate::{Lyric, LyricMeta};
+
+    fn sofietje() -> Lyric {
+        Lyric {
+            meta: LyricMeta {
+                artist: Some("Drs. P".to_owned()),
+                composer: Some("Drs. P & co".to_owned()),
+                key: Some("G".to_owned()),
+                tempo: Some(96),
+                language: Some("nl".to_owned()),
+                ..Default::default()
+            },
+            tags: vec!["cabaret".to_owned()],
+            ..Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![]).with_parts(
+                "{label: Verse 1}\n[G]Zij dronk  <ranja> met een [D]rietje\n\n{label: Refrein}\nSofietje\n\n\n{arrangement: Verse 1, Refrein, Refrein}"
+                    .parse()
+                    .unwrap(),
+            )
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(verse_name("Verse 1").as_deref(), Some("v1"));
+        assert_eq!(verse_name("Chorus").as_deref(), Some("c"));
+        assert_eq!(verse_name("Refrein"), None);
+        assert_eq!(verse_name("Verse one"), None);
+        assert_eq!(label("v1"), "Verse 1");
+        assert_eq!(label("p"), "Pre-Chorus");
+        assert_eq!(label("v1a"), "v1a");
+    }
+
+    #[test]
+    fn export() {
+        let xml = to_openlyrics(&sofietje());
+        assert!(xml.contains("<verseOrder>v1 o1 o1</verseOrder>"));
+        assert!(xml.contains(r#"<verse name="o1" lang="nl" lipl:label="Refrein">"#));
+        assert!(xml.contains(r#"<author type="music">Drs. P &amp; co</author>"#));
+        assert!(xml.contains(
+            r#"<verse name="v1" lang="nl"><lines><chord name="G"/>Zij dronk  &lt;ranja&gt; met een <chord name="D"/>rietje</lines></verse>"#
+        ));
+    }
+
+    #[test]
+    fn round_trip() {
+        let lyric = sofietje();
+        let lyrics = from_openlyrics(&to_collection([&lyric, &lyric])).unwrap();
+        assert_eq!(lyrics.len(), 2);
+        let imported = &lyrics[0];
+        assert_eq!(imported.title, lyric.title);
+        assert_eq!(imported.parts, lyric.parts);
+        assert_eq!(imported.chords, lyric.chords);
+        assert_eq!(imported.meta, lyric.meta);
+        assert_eq!(imported.tags, lyric.tags);
+        assert_eq!(imported.labels, lyric.labels);
+        assert_eq!(imported.arrangement, lyric.arrangement);
+    }
+
+    #[test]
+    fn import_layout() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<song xmlns="http://openlyrics.info/namespace/2009/song" version="0.9">
+  <properties>
+    <titles><title>Amazing Grace</title><title lang="nl">Genade</title></titles>
+    <authors><author type="words">John Newton</author></authors>
+  </properties>
+  <lyrics>
+    <verse name="c">
+      <lines>
+        Amazing <chord root="G" bass="B"/>grace<br/>
+        how sweet<comment>slow</comment> the sound
+      </lines>
+    </verse>
+  </lyrics>
+</song>"#;
+        let lyric = &from_openlyrics(xml).unwrap()[0];
+        assert_eq!(lyric.title, "Amazing Grace");
+        assert_eq!(lyric.meta.artist.as_deref(), Some("John Newton"));
+        assert_eq!(lyric.labels, vec![Some("Chorus".to_owned())]);
+        assert_eq!(
+            lyric.parts,
+            vec![vec![
+                "Amazing grace".to_owned(),
+                "how sweet the sound".to_owned()
+            ]]
+        );
+        assert_eq!(lyric.chords.as_ref().unwrap()[0][0][0].chord, "G/B");
+        assert_eq!(lyric.meta.notes, None);
+        assert!(from_openlyrics("<song><lyrics></song>").is_err());
+    }
+}
@@ -1,47 +1,299 @@
 use std::{
     io::{BufRead, BufReader, Error, Read},
-    iter::once,
+    iter::repeat_n,
     ops::Add,
     str::FromStr,
 };
 
+use crate::chord::{ChordAt, join_chords, split_chords};
+
 type Result<T> = std::result::Result<T, std::io::Error>;
 
-#[derive(Debug, PartialEq)]
-pub struct Parts(Vec<Vec<String>>);
+const ARRANGEMENT: &str = "arrangement";
+const TITLE: &str = "title";
+const KEY: &str = "key";
+const LABEL: &str = "label";
+const START_OF_CHORUS: [&str; 2] = ["soc", "start_of_chorus"];
+const END_OF_CHORUS: [&str; 2] = ["eoc", "end_of_chorus"];
+const CHORUS: &str = "Chorus";
+
+/// A verse, chorus or bridge. In text the optional label is a directive like `{label: Chorus}` on top of the part.
+/// A line like `[Chorus]` is sung text, so text written before parts had labels reads the same.
+///
+/// Chords are written inline in ChordPro notation, like `[G]Zij dronk [D]ranja`.
+/// They are kept apart from the sung text, one list of chords for every line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Part {
+    pub label: Option<String>,
+    pub lines: Vec<String>,
+    /// Empty if the part has no chords, otherwise as many as there are lines
+    pub chords: Vec<Vec<ChordAt>>,
+}
+
+impl Part {
+    pub fn to_text(&self) -> String {
+        let chords = self.chords.iter().map(Vec::as_slice).chain(repeat_n(
+            &[][..],
+            self.lines.len().saturating_sub(self.chords.len()),
+        ));
+        self.label
+            .iter()
+            .map(|label| format!("{{{LABEL}: {label}}}"))
+            .chain(
+                self.lines
+                    .iter()
+                    .zip(chords)
+                    .map(|(line, chords)| join_chords(line, chords)),
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 
-impl Default for Parts {
-    fn default() -> Self {
-        vec![].into()
+    fn has_chords(&self) -> bool {
+        self.chords.iter().any(|chords| !chords.is_empty())
     }
 }
 
+impl From<Vec<String>> for Part {
+    fn from(lines: Vec<String>) -> Self {
+        let (lines, chords): (Vec<_>, Vec<_>) = lines.iter().map(|line| split_chords(line)).unzip();
+        let part = Part {
+            label: None,
+            lines,
+            chords,
+        };
+        if part.has_chords() {
+            part
+        } else {
+            Part {
+                chords: vec![],
+                ..part
+            }
+        }
+    }
+}
+
+/// Parses a ChordPro directive like `{soc}` or `{key: G}` into a lowercase name and an optional value
+pub(crate) fn directive(line: &str) -> Option<(String, Option<&str>)> {
+    let inner = line.strip_prefix('{').and_then(|l| l.strip_suffix('}'))?;
+    let (name, value) = match inner.split_once(':') {
+        Some((name, value)) => (name.trim(), Some(value.trim())),
+        None => (inner.trim(), None),
+    };
+    (!name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+    .then(|| (name.to_ascii_lowercase(), value.filter(|v| !v.is_empty())))
+}
+
+/// The directives a part understands, a line with another name in braces is sung text
+fn is_known(name: &str) -> bool {
+    [ARRANGEMENT, TITLE, "t", KEY, LABEL]
+        .into_iter()
+        .chain(START_OF_CHORUS)
+        .chain(END_OF_CHORUS)
+        .any(|known| known == name)
+}
+
+/// Parses the value of a directive like `{arrangement: Verse 1, Chorus, Verse 2, Chorus}`
+fn arrangement(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The parts of a lyric, separated by blank lines in text.
+///
+/// The optional arrangement lists part labels in the order they are sung,
+/// so a chorus can be repeated without duplicating its text.
+/// The ChordPro directives `{title:}` and `{key:}` are kept, `{soc}` and `{eoc}`
+/// mark a part as chorus. Lines that look like other directives are kept as sung text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parts {
+    parts: Vec<Part>,
+    arrangement: Vec<String>,
+    title: Option<String>,
+    key: Option<String>,
+}
+
 impl Add<Vec<String>> for Parts {
     type Output = Parts;
     fn add(self, rhs: Vec<String>) -> Self::Output {
-        self.0
-            .into_iter()
-            .chain(once(rhs))
-            .collect::<Vec<_>>()
-            .into()
+        let mut parts = self;
+        let mut chorus = None;
+        let mut label = None;
+        let mut lines = vec![];
+        for line in rhs {
+            match directive(&line).filter(|(name, _)| is_known(name)) {
+                Some((name, value)) => match (name.as_str(), value) {
+                    (ARRANGEMENT, Some(value)) => parts.arrangement = arrangement(value),
+                    (TITLE | "t", value) => parts.title = value.map(String::from),
+                    (KEY, value) => parts.key = value.map(String::from),
+                    (LABEL, Some(value)) => label = Some(value.to_owned()),
+                    (name, value) if START_OF_CHORUS.contains(&name) => {
+                        chorus = Some(value.unwrap_or(CHORUS).to_owned())
+                    }
+                    _ => {}
+                },
+                None => lines.push(line),
+            }
+        }
+        if !lines.is_empty() {
+            parts.parts.push(Part {
+                label: label.or(chorus),
+                ..Part::from(lines)
+            });
+        }
+        parts
     }
 }
 
 impl From<Vec<Vec<String>>> for Parts {
     fn from(value: Vec<Vec<String>>) -> Self {
-        Parts(value)
+        Parts::new(
+            value
+                .into_iter()
+                .map(|lines| Part {
+                    label: None,
+                    lines,
+                    chords: vec![],
+                })
+                .collect(),
+            vec![],
+        )
     }
 }
 
 impl Parts {
+    pub fn new(parts: Vec<Part>, arrangement: Vec<String>) -> Self {
+        Self {
+            parts,
+            arrangement,
+            title: None,
+            key: None,
+        }
+    }
+
+    /// Combines the parts with the labels at the same position
+    pub fn labeled(
+        parts: Vec<Vec<String>>,
+        labels: Vec<Option<String>>,
+        arrangement: Vec<String>,
+    ) -> Self {
+        let labels = labels.into_iter().chain(std::iter::repeat(None));
+        Parts::new(
+            parts
+                .into_iter()
+                .zip(labels)
+                .map(|(lines, label)| Part {
+                    label,
+                    lines,
+                    chords: vec![],
+                })
+                .collect(),
+            arrangement,
+        )
+    }
+
+    /// Combines the parts with the chords at the same position
+    pub fn with_chords(self, chords: Vec<Vec<Vec<ChordAt>>>) -> Self {
+        let chords = chords.into_iter().chain(std::iter::repeat(vec![]));
+        Self {
+            parts: self
+                .parts
+                .into_iter()
+                .zip(chords)
+                .map(|(part, chords)| Part { chords, ..part })
+                .collect(),
+            ..self
+        }
+    }
+
     pub fn parts(&self) -> Vec<Vec<String>> {
-        self.0.clone()
+        self.parts.iter().map(|part| part.lines.clone()).collect()
+    }
+
+    /// The label of every part, empty if no part has a label
+    pub fn labels(&self) -> Vec<Option<String>> {
+        if self.parts.iter().all(|part| part.label.is_none()) {
+            vec![]
+        } else {
+            self.parts.iter().map(|part| part.label.clone()).collect()
+        }
+    }
+
+    /// The chords of every line of every part, empty if no part has chords
+    pub fn chords(&self) -> Vec<Vec<Vec<ChordAt>>> {
+        if self.parts.iter().any(Part::has_chords) {
+            self.parts
+                .iter()
+                .map(|part| {
+                    let missing = part.lines.len().saturating_sub(part.chords.len());
+                    part.chords
+                        .iter()
+                        .cloned()
+                        .chain(repeat_n(vec![], missing))
+                        .collect()
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn arrangement(&self) -> &[String] {
+        &self.arrangement
+    }
+
+    /// Value of the `{title:}` directive
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Value of the `{key:}` directive
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// The parts in the order they are written
+    pub fn written(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// The parts in the order they are sung. Without arrangement that is the order they are written in.
+    /// Labels in the arrangement that do not belong to a part are skipped.
+    pub fn arranged(&self) -> Vec<&Part> {
+        if self.arrangement.is_empty() {
+            self.parts.iter().collect()
+        } else {
+            self.arrangement
+                .iter()
+                .filter_map(|label| {
+                    self.parts
+                        .iter()
+                        .find(|part| part.label.as_ref() == Some(label))
+                })
+                .collect()
+        }
     }
 
     pub fn to_text(&self) -> String {
-        self.0
-            .iter()
-            .map(|part| part.join("\n"))
+        let directives = [(TITLE, &self.title), (KEY, &self.key)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| format!("{{{name}: {value}}}")))
+            .collect::<Vec<_>>();
+        (!directives.is_empty())
+            .then(|| directives.join("\n"))
+            .into_iter()
+            .chain(self.parts.iter().map(Part::to_text))
+            .chain(
+                (!self.arrangement.is_empty())
+                    .then(|| format!("{{{ARRANGEMENT}: {}}}", self.arrangement.join(", "))),
+            )
             .collect::<Vec<_>>()
             .join("\n\n")
     }
@@ -94,7 +346,8 @@ impl std::fmt::Display for Parts {
 
 #[cfg(test)]
 mod test {
-    use super::Parts;
+    use super::{Part, Parts};
+    use crate::chord::ChordAt;
 
     #[test]
     fn parse_part() {
@@ -125,4 +378,127 @@ mod test {
             *"Hallo allema\n\nJaJa\nNee",
         );
     }
+
+    #[test]
+    fn parse_labels_and_arrangement() {
+        let test = "{label: Verse 1}\nEen\n\n{ Label : Chorus }\nRefrein\n\n{Arrangement: Verse 1, Chorus, Verse 2, Chorus}\n\n{label: Verse 2}\nTwee";
+        let parts = test.parse::<Parts>().unwrap();
+        assert_eq!(
+            parts.labels(),
+            vec![
+                Some("Verse 1".to_owned()),
+                Some("Chorus".to_owned()),
+                Some("Verse 2".to_owned())
+            ]
+        );
+        assert_eq!(
+            parts.parts(),
+            vec![
+                vec!["Een".to_owned()],
+                vec!["Refrein".to_owned()],
+                vec!["Twee".to_owned()]
+            ]
+        );
+        assert_eq!(
+            parts
+                .arranged()
+                .into_iter()
+                .map(|part| part.lines[0].as_str())
+                .collect::<Vec<_>>(),
+            vec!["Een", "Refrein", "Twee", "Refrein"]
+        );
+        assert_eq!(
+            parts.to_text(),
+            "{label: Verse 1}\nEen\n\n{label: Chorus}\nRefrein\n\n{label: Verse 2}\nTwee\n\n{arrangement: Verse 1, Chorus, Verse 2, Chorus}"
+        );
+        assert_eq!(parts.to_text().parse::<Parts>().unwrap(), parts);
+    }
+
+    #[test]
+    fn unknown_directive_is_text() {
+        let parts = "{soc}\n{Sofie}\nZij dronk ranja\n{eoc}"
+            .parse::<Parts>()
+            .unwrap();
+        assert_eq!(
+            parts.parts(),
+            vec![vec!["{Sofie}".to_owned(), "Zij dronk ranja".to_owned()]]
+        );
+        assert_eq!(parts.labels(), vec![Some("Chorus".to_owned())]);
+    }
+
+    /// Text written before parts had labels
+    #[test]
+    fn bracketed_first_line_is_text() {
+        let parts = "[Intro]\nHm hm".parse::<Parts>().unwrap();
+        assert!(parts.labels().is_empty());
+        assert_eq!(
+            parts.parts(),
+            vec![vec!["[Intro]".to_owned(), "Hm hm".to_owned()]]
+        );
+        assert_eq!(parts.to_text(), "[Intro]\nHm hm");
+    }
+
+    #[test]
+    fn labeled() {
+        let parts = Parts::labeled(
+            vec![vec!["Een".to_owned()], vec!["Twee".to_owned()]],
+            vec![None, Some("Chorus".to_owned())],
+            vec![],
+        );
+        assert_eq!(
+            parts.arranged(),
+            vec![
+                &Part {
+                    label: None,
+                    lines: vec!["Een".to_owned()],
+                    chords: vec![]
+                },
+                &Part {
+                    label: Some("Chorus".to_owned()),
+                    lines: vec!["Twee".to_owned()],
+                    chords: vec![]
+                }
+            ]
+        );
+        assert_eq!(parts.to_text(), "Een\n\n{label: Chorus}\nTwee");
+        assert!(Parts::from(parts.parts()).labels().is_empty());
+    }
+
+    #[test]
+    fn chords_and_directives() {
+        let test = "{title: Sofietje}\n{key: G}\n\n[G]Zij dronk [D]ranja\nmet een rietje\n\n{soc}\n[G]\n[C]Sofie[G]tje\n{eoc}";
+        let parts = test.parse::<Parts>().unwrap();
+        assert_eq!(parts.title(), Some("Sofietje"));
+        assert_eq!(parts.key(), Some("G"));
+        assert_eq!(
+            parts.parts(),
+            vec![
+                vec!["Zij dronk ranja".to_owned(), "met een rietje".to_owned()],
+                vec!["".to_owned(), "Sofietje".to_owned()]
+            ]
+        );
+        assert_eq!(parts.labels(), vec![None, Some("Chorus".to_owned())]);
+        let chord = |position, chord: &str| ChordAt {
+            position,
+            chord: chord.to_owned(),
+        };
+        assert_eq!(
+            parts.chords(),
+            vec![
+                vec![vec![chord(0, "G"), chord(10, "D")], vec![]],
+                vec![vec![chord(0, "G")], vec![chord(0, "C"), chord(5, "G")]]
+            ]
+        );
+        assert_eq!(
+            parts.to_text(),
+            "{title: Sofietje}\n{key: G}\n\n[G]Zij dronk [D]ranja\nmet een rietje\n\n{label: Chorus}\n[G]\n[C]Sofie[G]tje"
+        );
+        assert_eq!(parts.to_text().parse::<Parts>().unwrap(), parts);
+        assert_eq!(
+            Parts::from(parts.parts())
+                .with_chords(parts.chords())
+                .chords(),
+            parts.chords()
+        );
+    }
 }
@@ -0,0 +1,138 @@
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{
+    Etag, Result,
+    error::{ErrInto, Error},
+    validation::Violation,
+};
+
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+/// A partial update of a lyric or playlist, see RFC 7396 and RFC 6902
+#[derive(Clone, Debug, PartialEq)]
+pub enum Patch {
+    Merge(Value),
+    Json(json_patch::Patch),
+}
+
+impl Patch {
+    /// Parses the body according to the media type in the Content-Type header
+    pub fn try_new(content_type: Option<&str>, body: &[u8]) -> Result<Self> {
+        let media_type = content_type
+            .and_then(|c| c.split(';').next())
+            .map(|m| m.trim().to_ascii_lowercase());
+        match media_type.as_deref() {
+            Some(MERGE_PATCH) => serde_json::from_slice(body).map(Self::Merge),
+            Some(JSON_PATCH) => serde_json::from_slice(body).map(Self::Json),
+            _ => return Err(Error::UnsupportedMediaType),
+        }
+        .map_err(|_| Error::Body)
+    }
+
+    pub fn apply<T>(&self, target: &T) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let mut document = serde_json::to_value(target).err_into()?;
+        match self {
+            Self::Merge(patch) => json_patch::merge(&mut document, patch),
+            Self::Json(patch) => json_patch::patch(&mut document, patch).map_err(|e| {
+                Error::Validation(vec![Violation::new(
+                    format!("patch[{}]", e.operation),
+                    e.kind.to_string(),
+                )])
+            })?,
+        };
+        serde_json::from_value(document)
+            .map_err(|e| Error::Validation(vec![Violation::new("patch", e.to_string())]))
+    }
+}
+
+/// Checks the If-Match precondition, applies the patch and makes sure the id stays the same
+pub fn patched<T>(
+    target: T,
+    id: impl Fn(&T) -> &str,
+    if_match: Option<&str>,
+    patch: &Patch,
+) -> Result<T>
+where
+    T: Serialize + DeserializeOwned + Etag,
+{
+    if if_match.is_some_and(|etag| etag != "*" && etag != target.etag()) {
+        return Err(Error::PreconditionFailed);
+    }
+    let result = patch.apply(&target)?;
+    if id(&result) == id(&target) {
+        Ok(result)
+    } else {
+        Err(Error::Validation(vec![Violation::new(
+            "id",
+            "cannot be changed",
+        )]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JSON_PATCH, MERGE_PATCH, Patch, patched};
+    use crate::{Etag, Lyric, error::Error};
+
+    fn lyric() -> Lyric {
+        Lyric::new(
+            "a".to_owned(),
+            "Sofietje".to_owned(),
+            vec![vec!["Zij dronk ranja met een rietje".to_owned()]],
+        )
+    }
+
+    fn id(lyric: &Lyric) -> &str {
+        &lyric.id
+    }
+
+    #[test]
+    fn merge_patch_keeps_parts() {
+        let patch = Patch::try_new(Some(MERGE_PATCH), br#"{"title":"Sofie"}"#).unwrap();
+        let result = patched(lyric(), id, None, &patch).unwrap();
+        assert_eq!(result.title, "Sofie");
+        assert_eq!(result.parts, lyric().parts);
+    }
+
+    #[test]
+    fn json_patch_with_matching_etag() {
+        let patch = Patch::try_new(
+            Some("application/json-patch+json; charset=utf-8"),
+            br#"[{"op":"replace","path":"/parts/0/0","value":"Op een Amsterdams terras"}]"#,
+        )
+        .unwrap();
+        let etag = lyric().etag();
+        let result = patched(lyric(), id, Some(&etag), &patch).unwrap();
+        assert_eq!(result.parts[0][0], "Op een Amsterdams terras");
+    }
+
+    #[test]
+    fn rejected_patches() {
+        let patch =
+            Patch::try_new(Some(JSON_PATCH), br#"[{"op":"remove","path":"/title"}]"#).unwrap();
+        assert!(matches!(
+            patched(lyric(), id, Some("other"), &patch),
+            Err(Error::PreconditionFailed)
+        ));
+        assert!(matches!(
+            patched(lyric(), id, None, &patch),
+            Err(Error::Validation(_))
+        ));
+
+        let patch = Patch::try_new(Some(MERGE_PATCH), br#"{"id":"b"}"#).unwrap();
+        assert!(matches!(
+            patched(lyric(), id, None, &patch),
+            Err(Error::Validation(_))
+        ));
+
+        assert!(matches!(
+            Patch::try_new(Some("application/json"), b"{}"),
+            Err(Error::UnsupportedMediaType)
+        ));
+    }
+}
@@ -1,11 +1,17 @@
-use base64::{Engine, engine::general_purpose::STANDARD_NO_PAD};
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+};
 use spin_sdk::{
     http::{HeaderMap, IntoResponse, StatusCode},
     wasip3::http_compat::http_into_wasi_response,
 };
 use std::hash::{DefaultHasher, Hash, Hasher};
 
-use crate::{Etag, error::Error};
+use crate::{
+    Etag,
+    error::{AuthenticationError, Error, violations_json},
+};
 
 pub fn unauthenticated() -> wasip3::http_compat::Response<String> {
     wasip3::http_compat::Response::builder()
@@ -15,11 +21,42 @@ pub fn unauthenticated() -> wasip3::http_compat::Response<String> {
         .unwrap()
 }
 
+/// The user name and password of a basic Authorization header
+pub fn basic_credentials(
+    authorization: Option<&str>,
+) -> Result<(String, String), AuthenticationError> {
+    let encoded = authorization
+        .ok_or(AuthenticationError::AuthenticationHeader)?
+        .strip_prefix("Basic ")
+        .ok_or(AuthenticationError::Unsupported)?;
+    let decoded = STANDARD.decode(encoded.trim())?;
+    let (username, password) = std::str::from_utf8(&decoded)?
+        .split_once(':')
+        .ok_or(AuthenticationError::AuthenticationHeader)?;
+    Ok((username.to_owned(), password.to_owned()))
+}
+
+/// Compares every byte, so the time taken does not tell how much of a secret was guessed right
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
 pub fn if_none_match(headers: &HeaderMap) -> Option<String> {
     headers
         .get("If-None-Match")
         .and_then(|h| h.to_str().ok())
-        .map(String::from)
+        .map(|etag| etag.trim_matches('"').to_owned())
+}
+
+pub fn if_match(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("If-Match")
+        .and_then(|h| h.to_str().ok())
+        .map(|etag| etag.trim_matches('"').to_owned())
 }
 
 impl IntoResponse for Error {
@@ -34,6 +71,15 @@ impl IntoResponse for Error {
             Self::NotFound => StatusCode::NOT_FOUND.into_response(),
             Self::Authentication(_) => http_into_wasi_response(unauthenticated()),
             Self::Body => StatusCode::BAD_REQUEST.into_response(),
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED.into_response(),
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+            Self::Validation(violations) => http_into_wasi_response(
+                wasip3::http_compat::Response::builder()
+                    .status(422)
+                    .header("Content-Type", "application/json")
+                    .body(violations_json(&violations))
+                    .unwrap(),
+            ),
             _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
         }
     }
@@ -48,3 +94,33 @@ impl<T: Hash> Etag for T {
         STANDARD_NO_PAD.encode(bytes)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{basic_credentials, constant_time_eq};
+    use crate::error::AuthenticationError;
+
+    #[test]
+    fn credentials() {
+        assert_eq!(
+            basic_credentials(Some("Basic cGF1bDpwYXNzd29yZA==")).unwrap(),
+            ("paul".to_owned(), "password".to_owned())
+        );
+        assert!(matches!(
+            basic_credentials(None),
+            Err(AuthenticationError::AuthenticationHeader)
+        ));
+        assert!(matches!(
+            basic_credentials(Some("Bearer cGF1bDpwYXNzd29yZA==")),
+            Err(AuthenticationError::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn constant_time() {
+        assert!(constant_time_eq("password", "password"));
+        assert!(!constant_time_eq("password", "passwork"));
+        assert!(!constant_time_eq("password", "pass"));
+        assert!(constant_time_eq("", ""));
+    }
+}
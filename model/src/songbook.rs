@@ -0,0 +1,183 @@
+//! Printable text and markdown representations of lyrics and playlists, without chords.
+//! Parts are written in the order they are sung.
+
+use crate::{Lyric, Playlist};
+
+pub const TEXT_PLAIN: &str = "text/plain";
+pub const TEXT_MARKDOWN: &str = "text/markdown";
+const APPLICATION_JSON: &str = "application/json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Text,
+    Markdown,
+}
+
+impl Format {
+    /// The supported media type in the Accept header with the highest quality, the first one of equal quality.
+    /// Json when there is none, a quality of zero means not acceptable.
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        accept
+            .into_iter()
+            .flat_map(|accept| accept.split(','))
+            .filter_map(|range| {
+                let mut parameters = range.split(';');
+                let format = match parameters.next()?.trim().to_ascii_lowercase().as_str() {
+                    TEXT_PLAIN => Self::Text,
+                    TEXT_MARKDOWN => Self::Markdown,
+                    APPLICATION_JSON | "application/*" | "*/*" => Self::Json,
+                    _ => return None,
+                };
+                let quality = parameters
+                    .filter_map(|parameter| parameter.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                    .map_or(Some(1.0), |(_, quality)| quality.trim().parse::<f32>().ok())?;
+                (quality > 0.0).then_some((format, quality))
+            })
+            .fold(None, |best, (format, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((format, quality)),
+            })
+            .map_or(Self::Json, |(format, _)| format)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Text => "text/plain; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    fn heading(&self, level: usize, title: &str) -> String {
+        match self {
+            Self::Markdown => format!("{} {title}", "#".repeat(level)),
+            _ => format!("{title}\n{}", "=".repeat(title.chars().count())),
+        }
+    }
+
+    fn label(&self, label: &str) -> String {
+        match self {
+            Self::Markdown => format!("*{label}*"),
+            _ => format!("[{label}]"),
+        }
+    }
+
+    /// Markdown needs two trailing spaces for a line break within a paragraph
+    fn line_end(&self) -> &'static str {
+        match self {
+            Self::Markdown => "  \n",
+            _ => "\n",
+        }
+    }
+}
+
+fn body(lyric: &Lyric, format: Format) -> String {
+    lyric
+        .to_parts()
+        .arranged()
+        .into_iter()
+        .map(|part| {
+            part.label
+                .iter()
+                .map(|label| format.label(label))
+                .chain(part.lines.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(format.line_end())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn song(title: String, lyric: &Lyric, format: Format, level: usize) -> String {
+    let body = body(lyric, format);
+    if body.is_empty() {
+        format.heading(level, &title)
+    } else {
+        format!("{}\n\n{body}", format.heading(level, &title))
+    }
+}
+
+pub fn lyric(lyric: &Lyric, format: Format) -> String {
+    song(lyric.title.clone(), lyric, format, 1) + "\n"
+}
+
+/// The playlist title followed by the numbered lyrics
+pub fn playlist(playlist: &Playlist, lyrics: &[Lyric], format: Format) -> String {
+    let songs = lyrics
+        .iter()
+        .enumerate()
+        .map(|(i, lyric)| song(format!("{}. {}", i + 1, lyric.title), lyric, format, 2));
+    std::iter::once(format.heading(1, &playlist.title))
+        .chain(songs)
+        .collect::<Vec<_>>()
+        .join("\n\n\n")
+        + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Format, lyric, playlist};
+    use crate::{Lyric, Playlist};
+
+    fn sofietje() -> Lyric {
+        Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![]).with_parts(
+            "[G]Zij dronk ranja\nmet een rietje\n\n{label: Chorus}\nSofietje\n\n{arrangement: Chorus, Chorus}"
+                .parse()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn accept() {
+        assert_eq!(Format::from_accept(None), Format::Json);
+        assert_eq!(
+            Format::from_accept(Some("text/html, text/markdown;q=0.9, */*;q=0.1")),
+            Format::Markdown
+        );
+        assert_eq!(Format::from_accept(Some("Text/Plain")), Format::Text);
+        assert_eq!(Format::from_accept(Some("image/png")), Format::Json);
+        assert_eq!(
+            Format::from_accept(Some("text/plain;q=0.5, text/markdown")),
+            Format::Markdown
+        );
+        assert_eq!(
+            Format::from_accept(Some("application/json;q=0.5, text/plain")),
+            Format::Text
+        );
+        assert_eq!(
+            Format::from_accept(Some("text/plain;q=0, text/markdown; q=0")),
+            Format::Json
+        );
+    }
+
+    #[test]
+    fn lyric_as_text_and_markdown() {
+        assert_eq!(
+            lyric(&sofietje(), Format::Text),
+            "Sofietje\n========\n\n[Chorus]\nSofietje\n\n[Chorus]\nSofietje\n"
+        );
+        let lyric = Lyric::new(
+            "b".to_owned(),
+            "Dodenrit".to_owned(),
+            vec![vec![
+                "Over de heide".to_owned(),
+                "Rijdt een ruiter".to_owned(),
+            ]],
+        );
+        assert_eq!(
+            super::lyric(&lyric, Format::Markdown),
+            "# Dodenrit\n\nOver de heide  \nRijdt een ruiter\n"
+        );
+    }
+
+    #[test]
+    fn playlist_as_markdown() {
+        let list = Playlist::new("p".to_owned(), "Kerst".to_owned(), vec!["a".into()]);
+        assert_eq!(
+            playlist(&list, &[sofietje()], Format::Markdown),
+            "# Kerst\n\n\n## 1. Sofietje\n\n*Chorus*  \nSofietje\n\n*Chorus*  \nSofietje\n"
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{
+    Db, Lyric, LyricMeta, MemberMove, Playlist, TagPost, Tenant, chord::is_chord, member::Member,
+};
+
+pub const MAX_TITLE_LENGTH: usize = 200;
+pub const MAX_LINE_LENGTH: usize = 500;
+pub const MAX_DETAIL_LENGTH: usize = 200;
+pub const MAX_NOTES_LENGTH: usize = 2000;
+
+/// The database label of the app itself, when `lipl_database` is not set
+pub const DEFAULT_LABEL: &str = "default";
+
+/// A field that does not pass validation, reported with status 422
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+impl Violation {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn unknown_lyric(field: impl Into<String>) -> Self {
+        Self::new(field, "unknown lyric")
+    }
+
+    pub fn duplicate_title(field: impl Into<String>) -> Self {
+        Self::new(field, "title already in use")
+    }
+
+    pub fn duplicate_id(field: impl Into<String>) -> Self {
+        Self::new(field, "id already in use")
+    }
+
+    pub fn prefixed(self, prefix: &str) -> Self {
+        Self {
+            field: format!("{prefix}.{}", self.field),
+            ..self
+        }
+    }
+}
+
+/// Checks that can be done without consulting the database
+pub trait Validate {
+    fn violations(&self) -> Vec<Violation>;
+}
+
+fn check_title(title: &str) -> Option<Violation> {
+    if title.trim().is_empty() {
+        Some(Violation::new("title", "must not be empty"))
+    } else {
+        check_length("title", title, MAX_TITLE_LENGTH)
+    }
+}
+
+fn check_length(field: impl Into<String>, value: &str, max: usize) -> Option<Violation> {
+    (value.chars().count() > max)
+        .then(|| Violation::new(field, format!("must be at most {max} characters")))
+}
+
+fn check_optional(field: &str, value: Option<&String>) -> Option<Violation> {
+    value.and_then(|v| check_length(field, v, MAX_DETAIL_LENGTH))
+}
+
+/// Characters that would change the meaning of a label in the text representation of the parts
+const LABEL_RESERVED: [char; 7] = ['[', ']', '{', '}', ',', '\n', '\r'];
+
+impl Lyric {
+    fn label_violations(&self) -> Vec<Violation> {
+        let count = (self.labels.len() > self.parts.len())
+            .then(|| Violation::new("labels", "must not outnumber the parts"));
+        let labels = self.labels.iter().enumerate().filter_map(|(i, label)| {
+            label.as_ref().and_then(|label| {
+                if label.trim().is_empty() || label.contains(LABEL_RESERVED) {
+                    Some(Violation::new(
+                        format!("labels[{i}]"),
+                        "must not be empty or contain brackets, braces or commas",
+                    ))
+                } else {
+                    check_length(format!("labels[{i}]"), label, MAX_DETAIL_LENGTH)
+                }
+            })
+        });
+        let mut seen = HashSet::new();
+        let duplicates = self
+            .labels
+            .iter()
+            .enumerate()
+            .filter(move |(_, label)| label.as_ref().is_some_and(|label| !seen.insert(label)))
+            .map(|(i, _)| Violation::new(format!("labels[{i}]"), "already in use"));
+        let arrangement = self
+            .arrangement
+            .iter()
+            .enumerate()
+            .filter(|(_, label)| !self.labels.contains(&Some(label.to_string())))
+            .map(|(i, _)| Violation::new(format!("arrangement[{i}]"), "unknown label"));
+        count
+            .into_iter()
+            .chain(labels)
+            .chain(duplicates)
+            .chain(arrangement)
+            .collect()
+    }
+
+    fn chord_violations(&self) -> Vec<Violation> {
+        let Some(chords) = &self.chords else {
+            return vec![];
+        };
+        let count = (chords.len() > self.parts.len())
+            .then(|| Violation::new("chords", "must not outnumber the parts"));
+        let lines = chords.iter().enumerate().flat_map(|(i, part)| {
+            let lines = self.parts.get(i).map(Vec::as_slice).unwrap_or_default();
+            let count = (part.len() > lines.len())
+                .then(|| Violation::new(format!("chords[{i}]"), "must not outnumber the lines"));
+            let chords = part.iter().enumerate().flat_map(move |(j, line)| {
+                let length = lines.get(j).map(|l| l.chars().count()).unwrap_or_default();
+                line.iter().enumerate().filter_map(move |(k, chord)| {
+                    let field = format!("chords[{i}][{j}][{k}]");
+                    if !is_chord(&chord.chord) {
+                        Some(Violation::new(format!("{field}.chord"), "not a chord"))
+                    } else if chord.position > length {
+                        Some(Violation::new(
+                            format!("{field}.position"),
+                            "beyond the end of the line",
+                        ))
+                    } else if k > 0 && chord.position < line[k - 1].position {
+                        Some(Violation::new(
+                            format!("{field}.position"),
+                            "must not be before the previous chord",
+                        ))
+                    } else {
+                        None
+                    }
+                })
+            });
+            count.into_iter().chain(chords)
+        });
+        count.into_iter().chain(lines).collect()
+    }
+}
+
+impl Validate for LyricMeta {
+    fn violations(&self) -> Vec<Violation> {
+        [
+            check_optional("artist", self.artist.as_ref()),
+            check_optional("composer", self.composer.as_ref()),
+            check_optional("copyright", self.copyright.as_ref()),
+            check_optional("key", self.key.as_ref()),
+            check_optional("language", self.language.as_ref()),
+            self.notes
+                .as_ref()
+                .and_then(|notes| check_length("notes", notes, MAX_NOTES_LENGTH)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl Validate for Lyric {
+    fn violations(&self) -> Vec<Violation> {
+        let lines = self.parts.iter().enumerate().flat_map(|(i, part)| {
+            part.iter().enumerate().filter_map(move |(j, line)| {
+                check_length(format!("parts[{i}][{j}]"), line, MAX_LINE_LENGTH)
+            })
+        });
+        let tags = self.tags.iter().enumerate().filter_map(|(i, tag)| {
+            if tag.trim().is_empty() {
+                Some(Violation::new(format!("tags[{i}]"), "must not be empty"))
+            } else {
+                check_length(format!("tags[{i}]"), tag, MAX_DETAIL_LENGTH)
+            }
+        });
+        check_title(&self.title)
+            .into_iter()
+            .chain(lines)
+            .chain(self.label_violations())
+            .chain(self.chord_violations())
+            .chain(self.meta.violations())
+            .chain(tags)
+            .collect()
+    }
+}
+
+impl Validate for TagPost {
+    fn violations(&self) -> Vec<Violation> {
+        if self.name.trim().is_empty() {
+            vec![Violation::new("name", "must not be empty")]
+        } else {
+            check_length("name", &self.name, MAX_DETAIL_LENGTH)
+                .into_iter()
+                .collect()
+        }
+    }
+}
+
+/// The name is a database label and part of a path, so only lowercase letters, digits and dashes.
+/// `default` is the label of the database of the app itself.
+impl Validate for Tenant {
+    fn violations(&self) -> Vec<Violation> {
+        let name = if self.name.is_empty() {
+            Some(Violation::new("name", "must not be empty"))
+        } else if self.name == DEFAULT_LABEL {
+            Some(Violation::new("name", "is reserved"))
+        } else if !self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            Some(Violation::new(
+                "name",
+                "only lowercase letters, digits and dashes",
+            ))
+        } else {
+            check_length("name", &self.name, MAX_DETAIL_LENGTH)
+        };
+        let username = if self.username.is_empty() {
+            Some(Violation::new("username", "must not be empty"))
+        } else {
+            check_length("username", &self.username, MAX_DETAIL_LENGTH)
+        };
+        let password = self
+            .password
+            .is_empty()
+            .then(|| Violation::new("password", "must not be empty"));
+        [
+            name,
+            check_optional("host", self.host.as_ref()),
+            username,
+            password,
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl Validate for Member {
+    fn violations(&self) -> Vec<Violation> {
+        let details = &self.details;
+        [
+            self.lyric_id
+                .is_empty()
+                .then(|| Violation::new("lyric_id", "must not be empty")),
+            check_optional("key", details.key.as_ref()),
+            check_optional("notes", details.notes.as_ref()),
+            check_optional("segment", details.segment.as_ref()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+impl Validate for Playlist {
+    fn violations(&self) -> Vec<Violation> {
+        let members = self.members.iter().enumerate().flat_map(|(i, member)| {
+            member
+                .violations()
+                .into_iter()
+                .map(move |v| v.prefixed(&format!("members[{i}]")))
+        });
+        check_title(&self.title)
+            .into_iter()
+            .chain(members)
+            .collect()
+    }
+}
+
+/// Every value that was seen before in the list, reported with `violation` at its index
+fn duplicates<'a>(
+    prefix: &'a str,
+    values: impl Iterator<Item = &'a String> + 'a,
+    violation: impl Fn(String) -> Violation + 'a,
+) -> impl Iterator<Item = Violation> + 'a {
+    let mut seen = HashSet::new();
+    values
+        .enumerate()
+        .filter(move |(_, value)| !seen.insert(*value))
+        .map(move |(i, _)| violation(format!("{prefix}[{i}]")))
+}
+
+fn duplicate_ids<'a>(
+    prefix: &'a str,
+    ids: impl Iterator<Item = &'a String> + 'a,
+) -> impl Iterator<Item = Violation> + 'a {
+    duplicates(prefix, ids, |field| Violation::duplicate_id(field + ".id"))
+}
+
+fn duplicate_titles<'a>(
+    prefix: &'a str,
+    titles: impl Iterator<Item = &'a String> + 'a,
+) -> impl Iterator<Item = Violation> + 'a {
+    duplicates(prefix, titles, |field| {
+        Violation::duplicate_title(field + ".title")
+    })
+}
+
+/// Moves of the members of a playlist with `count` members, in the order of the request body
+pub fn member_move_violations(moves: &[MemberMove], count: usize) -> Vec<Violation> {
+    moves
+        .iter()
+        .enumerate()
+        .flat_map(|(i, m)| [("from", m.from), ("to", m.to)].map(move |p| (i, p)))
+        .filter(|(_, (_, position))| *position >= count)
+        .map(|(i, (field, _))| {
+            Violation::new(
+                format!("[{i}].{field}"),
+                format!("must be less than {count}"),
+            )
+        })
+        .collect()
+}
+
+impl Validate for Db {
+    fn violations(&self) -> Vec<Violation> {
+        let lyric_ids = self
+            .lyrics
+            .iter()
+            .map(|lyric| &lyric.id)
+            .collect::<HashSet<_>>();
+        let lyrics = self.lyrics.iter().enumerate().flat_map(|(i, lyric)| {
+            lyric
+                .violations()
+                .into_iter()
+                .map(move |v| v.prefixed(&format!("lyrics[{i}]")))
+        });
+        let playlists = self.playlists.iter().enumerate().flat_map(|(i, playlist)| {
+            let unknown = playlist
+                .members
+                .iter()
+                .enumerate()
+                .filter(|(_, member)| !lyric_ids.contains(&member.lyric_id))
+                .map(|(j, _)| Violation::unknown_lyric(format!("members[{j}].lyric_id")));
+            playlist
+                .violations()
+                .into_iter()
+                .chain(unknown)
+                .map(move |v| v.prefixed(&format!("playlists[{i}]")))
+                .collect::<Vec<_>>()
+        });
+        lyrics
+            .chain(duplicate_ids("lyrics", self.lyrics.iter().map(|l| &l.id)))
+            .chain(duplicate_titles(
+                "lyrics",
+                self.lyrics.iter().map(|l| &l.title),
+            ))
+            .chain(playlists)
+            .chain(duplicate_ids(
+                "playlists",
+                self.playlists.iter().map(|p| &p.id),
+            ))
+            .chain(duplicate_titles(
+                "playlists",
+                self.playlists.iter().map(|p| &p.title),
+            ))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MAX_TITLE_LENGTH, Validate, Violation, member_move_violations};
+    use crate::{Db, Lyric, MemberMove, Playlist, Tenant, chord::ChordAt};
+
+    #[test]
+    fn tenant_name() {
+        let tenant = |name: &str| Tenant {
+            name: name.to_owned(),
+            host: None,
+            username: "koor".to_owned(),
+            password: "geheim".to_owned(),
+        };
+        assert!(tenant("koor-1").violations().is_empty());
+        for name in ["", "Koor", "koor/1", "../koor", "default"] {
+            assert_eq!(tenant(name).violations()[0].field, "name", "{name}");
+        }
+        let without_credentials = Tenant {
+            username: String::new(),
+            password: String::new(),
+            ..tenant("koor")
+        };
+        assert_eq!(
+            without_credentials
+                .violations()
+                .iter()
+                .map(|violation| violation.field.as_str())
+                .collect::<Vec<_>>(),
+            vec!["username", "password"]
+        );
+    }
+
+    #[test]
+    fn valid_lyric() {
+        let lyric = Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![]);
+        assert!(lyric.violations().is_empty());
+    }
+
+    #[test]
+    fn labels_and_arrangement() {
+        let lyric = Lyric {
+            labels: vec![
+                Some("Chorus".to_owned()),
+                Some("[Verse]".to_owned()),
+                Some("Chorus".to_owned()),
+            ],
+            arrangement: vec!["Chorus".to_owned(), "Bridge".to_owned()],
+            ..Lyric::new(
+                "a".to_owned(),
+                "Sofietje".to_owned(),
+                vec![
+                    vec!["Een".to_owned()],
+                    vec!["Twee".to_owned()],
+                    vec!["Drie".to_owned()],
+                ],
+            )
+        };
+        assert_eq!(
+            lyric
+                .violations()
+                .into_iter()
+                .map(|v| v.field)
+                .collect::<Vec<_>>(),
+            vec!["labels[1]", "labels[2]", "arrangement[1]"]
+        );
+    }
+
+    #[test]
+    fn chords() {
+        let lyric = Lyric {
+            chords: Some(vec![vec![vec![
+                ChordAt {
+                    position: 0,
+                    chord: "Chorus".to_owned(),
+                },
+                ChordAt {
+                    position: 4,
+                    chord: "G".to_owned(),
+                },
+            ]]]),
+            ..Lyric::new(
+                "a".to_owned(),
+                "Sofietje".to_owned(),
+                vec![vec!["Een".to_owned()]],
+            )
+        };
+        assert_eq!(
+            lyric
+                .violations()
+                .into_iter()
+                .map(|v| v.field)
+                .collect::<Vec<_>>(),
+            vec!["chords[0][0][0].chord", "chords[0][0][1].position"]
+        );
+    }
+
+    #[test]
+    fn empty_and_overlong_titles() {
+        let empty = Lyric::new("a".to_owned(), " ".to_owned(), vec![]);
+        assert_eq!(
+            empty.violations(),
+            vec![Violation::new("title", "must not be empty")]
+        );
+
+        let long = Playlist::new("a".to_owned(), "x".repeat(MAX_TITLE_LENGTH + 1), vec![]);
+        assert_eq!(long.violations()[0].field, "title");
+    }
+
+    #[test]
+    fn db_references_and_duplicates() {
+        let db = Db {
+            lyrics: vec![
+                Lyric::new("a".to_owned(), "Sofietje".to_owned(), vec![]),
+                Lyric::new("b".to_owned(), "Sofietje".to_owned(), vec![]),
+                Lyric::new("a".to_owned(), "Dodenrit".to_owned(), vec![]),
+            ],
+            playlists: vec![
+                Playlist::new(
+                    "p".to_owned(),
+                    "Alles".to_owned(),
+                    vec!["a".into(), "c".into()],
+                ),
+                Playlist::new("p".to_owned(), "Niets".to_owned(), vec![]),
+            ],
+        };
+        assert_eq!(
+            db.violations(),
+            vec![
+                Violation::duplicate_id("lyrics[2].id"),
+                Violation::duplicate_title("lyrics[1].title"),
+                Violation::unknown_lyric("playlists[0].members[1].lyric_id"),
+                Violation::duplicate_id("playlists[1].id"),
+            ]
+        );
+    }
+
+    #[test]
+    fn member_moves() {
+        let moves = [MemberMove { from: 0, to: 2 }, MemberMove { from: 3, to: 1 }];
+        assert_eq!(
+            member_move_violations(&moves, 3),
+            vec![Violation::new("[1].from", "must be less than 3")]
+        );
+        assert!(member_move_violations(&moves, 4).is_empty());
+    }
+}
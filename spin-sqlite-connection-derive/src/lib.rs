@@ -0,0 +1,147 @@
+//! `#[derive(FromRow)]` for [spin-sqlite-connection](https://crates.io/crates/spin-sqlite-connection).
+//!
+//! Every field is read from the column with the same name. Attributes on the struct:
+//!
+//! - `#[row(error = "Type")]` the error of the conversion, it needs `From<ColumnError>`.
+//!   Defaults to `ColumnError`.
+//!
+//! Attributes on a field:
+//!
+//! - `#[row(column = "name")]` read another column, required for tuple struct fields
+//! - `#[row(with = "path")]` convert the column value with a fallible function
+//! - `#[row(flatten)]` read the field as a row of its own, from the same columns
+//! - `#[row(skip)]` not read from the row, the field gets its default value
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, LitStr, Path, Result,
+    Type,
+};
+
+#[proc_macro_derive(FromRow, attributes(row))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_row(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Source {
+    Column { column: String, with: Option<Path> },
+    Flatten,
+    Skip,
+}
+
+fn error_type(input: &DeriveInput) -> Result<Type> {
+    let mut error = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("row"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("error") {
+                error = Some(meta.value()?.parse::<LitStr>()?.parse::<Type>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `error`"))
+            }
+        })?;
+    }
+    Ok(error.unwrap_or_else(|| syn::parse_quote!(::spin_sqlite_connection::ColumnError)))
+}
+
+fn source(field: &syn::Field) -> Result<Source> {
+    let mut column = field.ident.as_ref().map(ToString::to_string);
+    let mut with = None;
+    let mut flatten = false;
+    let mut skip = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("row"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("column") {
+                column = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("with") {
+                with = Some(meta.value()?.parse::<LitStr>()?.parse::<Path>()?);
+            } else if meta.path.is_ident("flatten") {
+                flatten = true;
+            } else if meta.path.is_ident("skip") {
+                skip = true;
+            } else {
+                return Err(meta.error("expected `column`, `with`, `flatten` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    match (skip, flatten, column) {
+        (true, _, _) => Ok(Source::Skip),
+        (false, true, _) => Ok(Source::Flatten),
+        (false, false, Some(column)) => Ok(Source::Column { column, with }),
+        (false, false, None) => Err(Error::new(
+            field.span(),
+            "a tuple struct field needs #[row(column = \"name\")]",
+        )),
+    }
+}
+
+fn value(field: &syn::Field) -> Result<TokenStream2> {
+    Ok(match source(field)? {
+        Source::Column { column, with: None } => quote! { row.get(#column)? },
+        Source::Column {
+            column,
+            with: Some(with),
+        } => quote! { #with(row.get(#column)?)? },
+        Source::Flatten => quote! { ::spin_sqlite_connection::FromRow::from_row(row)? },
+        Source::Skip => quote! { ::core::default::Default::default() },
+    })
+}
+
+fn from_row(input: DeriveInput) -> Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            input.span(),
+            "FromRow can only be derived for a struct",
+        ));
+    };
+    let construct = match &data.fields {
+        Fields::Named(fields) => {
+            let fields = fields
+                .named
+                .iter()
+                .map(|field| {
+                    let name = &field.ident;
+                    value(field).map(|value| quote! { #name: #value })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote! { Self { #(#fields),* } }
+        }
+        Fields::Unnamed(fields) => {
+            let fields = fields
+                .unnamed
+                .iter()
+                .map(value)
+                .collect::<Result<Vec<_>>>()?;
+            quote! { Self(#(#fields),*) }
+        }
+        Fields::Unit => quote! { { let _ = row; Self } },
+    };
+    let error = error_type(&input)?;
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::spin_sqlite_connection::FromRow for #name #type_generics #where_clause {
+            type Error = #error;
+
+            fn from_row(
+                row: &::spin_sqlite_connection::Row<'_>,
+            ) -> ::core::result::Result<Self, Self::Error> {
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+    })
+}
@@ -1,52 +1,185 @@
-use spin_sdk::sqlite::{Error, QueryResult, RowResult, Value};
-use std::marker::PhantomData;
+use futures::{future, Stream, StreamExt, TryStreamExt};
+use spin_sdk::sqlite::Error;
+use std::{marker::PhantomData, sync::atomic::AtomicUsize};
+
+use crate::{
+    params::Params,
+    row::{FromRow, Row},
+    storage::{Rows, Storage},
+    transaction::Level,
+};
+
+/// The statements of `sql`, which end with a `;` and may span lines.
+/// Lines starting with `--` are comments.
+fn statements(sql: &str) -> Vec<String> {
+    sql.lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .split(';')
+        .map(|statement| statement.trim())
+        .filter(|statement| !statement.is_empty())
+        .map(|statement| statement.to_owned())
+        .collect()
+}
+
+/// The user_version of a database with all `upgrades` applied
+pub fn schema_version(upgrades: &str) -> usize {
+    statements(upgrades).len()
+}
+
+/// Called with the error that caused a rollback and the error of the rollback itself
+type RollbackFailure<E> = Box<dyn Fn(&E, &E) + Send + Sync>;
 
 pub struct SqliteConnection<E>
 where
     E: From<Error>,
 {
-    inner: spin_sdk::sqlite::Connection,
+    inner: Box<dyn Storage>,
+    /// Number of transactions that are open, nested ones included
+    transactions: AtomicUsize,
+    rollback_failure: RollbackFailure<E>,
     phantomdata: PhantomData<E>,
 }
 
 impl<E: From<Error>> SqliteConnection<E> {
     pub async fn try_open_default(migrations: Option<&str>) -> Result<Self, E> {
-        let connection = spin_sdk::sqlite::Connection::open_default().await?;
-        if let Some(statements) = migrations {
-            for statement in statements.split('\n').map(|line| line.trim()) {
-                connection.execute(statement, []).await?;
-            }
-        }
-        Ok(Self {
-            inner: connection,
+        Self::open("default", migrations).await
+    }
+
+    /// Opens the database of the Spin host with `label`, which must be listed in the
+    /// `sqlite_databases` of the component, after applying the statements in `migrations`
+    pub async fn open(label: &str, migrations: Option<&str>) -> Result<Self, E> {
+        let connection = spin_sdk::sqlite::Connection::open(label).await?;
+        Self::try_open(connection, migrations).await
+    }
+
+    /// Runs the statements on `storage` instead of the default database of the Spin host,
+    /// after applying the statements in `migrations`
+    pub async fn try_open(
+        storage: impl Storage + 'static,
+        migrations: Option<&str>,
+    ) -> Result<Self, E> {
+        let connection = Self {
+            inner: Box::new(storage),
+            transactions: AtomicUsize::new(0),
+            rollback_failure: Box::new(|_, _| {}),
             phantomdata: PhantomData,
-        })
+        };
+        for statement in migrations.map(statements).unwrap_or_default() {
+            connection.execute(statement, Params::None).await?;
+        }
+        Ok(connection)
     }
 
-    pub async fn query<T>(&self, sql: impl AsRef<str>, parameters: Vec<Value>) -> Result<Vec<T>, E>
+    /// Called when a rollback fails, with the error that caused the rollback and the error of the rollback
+    pub fn with_rollback_failure(
+        self,
+        rollback_failure: impl Fn(&E, &E) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            rollback_failure: Box::new(rollback_failure),
+            ..self
+        }
+    }
+
+    /// Runs `f` in a transaction, which is committed when it returns `Ok` and rolled back on `Err`
+    /// or when the commit fails. A transaction inside another one is a savepoint, so a failure
+    /// only undoes its own changes and the outer transaction decides what happens to the rest.
+    pub async fn transaction<T>(&self, f: impl AsyncFnOnce(&Self) -> Result<T, E>) -> Result<T, E> {
+        let level = Level::enter(&self.transactions);
+        self.execute_all(level.begin()).await?;
+        let error = match f(self).await {
+            Ok(value) => match self.execute_all(level.commit()).await {
+                Ok(()) => return Ok(value),
+                Err(error) => error,
+            },
+            Err(error) => error,
+        };
+        if let Err(rollback) = self.execute_all(level.roll_back()).await {
+            (self.rollback_failure)(&error, &rollback);
+        }
+        Err(error)
+    }
+
+    async fn execute_all(&self, statements: Vec<String>) -> Result<(), E> {
+        for statement in statements {
+            self.execute(statement, Params::None).await?;
+        }
+        Ok(())
+    }
+
+    /// Applies the statements in `upgrades` that were not applied before.
+    /// The number of applied statements is kept in the user_version pragma of the database,
+    /// which is raised in the same transaction as the statement it counts.
+    pub async fn upgrade(&self, upgrades: &str) -> Result<(), E> {
+        let (_, rows) = self.rows("PRAGMA user_version", Params::None).await?;
+        let version = rows
+            .try_collect::<Vec<_>>()
+            .await?
+            .first()
+            .and_then(|row| row.get::<usize>(0))
+            .unwrap_or_default();
+        for (index, statement) in statements(upgrades).into_iter().enumerate().skip(version) {
+            self.transaction(async |connection| {
+                connection.execute(&statement, Params::None).await?;
+                connection
+                    .execute(format!("PRAGMA user_version = {}", index + 1), Params::None)
+                    .await
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn query<T>(
+        &self,
+        sql: impl AsRef<str>,
+        parameters: impl Into<Params>,
+    ) -> Result<Vec<T>, E>
     where
-        T: TryFrom<RowResult, Error = E>,
+        T: FromRow,
+        E: From<T::Error>,
     {
-        let query_result = self.query_result(sql, parameters).await?;
-        let rows = query_result.collect().await?;
-        rows.into_iter().map(T::try_from).collect()
+        let (columns, rows) = self.rows(sql.as_ref(), parameters).await?;
+        rows.try_collect::<Vec<_>>()
+            .await?
+            .iter()
+            .map(|row| T::from_row(&Row::new(&columns, row)).map_err(E::from))
+            .collect()
     }
 
-    async fn query_result<S>(&self, sql: S, parameters: Vec<Value>) -> Result<QueryResult, E>
+    /// Converts the rows as they arrive from the host instead of collecting them first,
+    /// an error of the query as a whole is the last item of the stream
+    pub async fn query_stream<T>(
+        &self,
+        sql: impl AsRef<str>,
+        parameters: impl Into<Params>,
+    ) -> Result<impl Stream<Item = Result<T, E>> + 'static, E>
     where
-        S: AsRef<str>,
+        T: FromRow + 'static,
+        E: From<T::Error> + 'static,
     {
-        self.inner
-            .execute(sql.as_ref(), parameters)
-            .await
-            .map_err(E::from)
+        let (columns, rows) = self.rows(sql.as_ref(), parameters).await?;
+        Ok(rows.map(move |row| T::from_row(&Row::new(&columns, &row?)).map_err(E::from)))
+    }
+
+    async fn rows(
+        &self,
+        sql: &str,
+        parameters: impl Into<Params>,
+    ) -> Result<(Vec<String>, Rows), E> {
+        let parameters = parameters.into().bind(sql)?;
+        self.inner.query(sql, parameters).await.map_err(E::from)
     }
 
-    pub async fn execute<S>(&self, sql: S, parameters: Vec<Value>) -> Result<u64, E>
+    /// Runs `sql` to the end, so an error of a later step is returned as well
+    pub async fn execute<S>(&self, sql: S, parameters: impl Into<Params>) -> Result<u64, E>
     where
         S: AsRef<str>,
     {
-        self.inner.execute(sql.as_ref(), parameters).await?;
+        let (_, rows) = self.rows(sql.as_ref(), parameters).await?;
+        rows.try_for_each(|_| future::ready(Ok(()))).await?;
         Ok(self.inner.changes().await)
     }
 
@@ -1,5 +1,21 @@
 #![doc = include_str!("../README.md")]
 
+// The derive macro refers to this crate by name, also in its own tests
+#[cfg(test)]
+extern crate self as spin_sqlite_connection;
+
 mod connection;
+#[cfg(feature = "rusqlite")]
+mod native;
+mod params;
+mod row;
+mod storage;
+mod transaction;
 
-pub use connection::SqliteConnection;
+pub use connection::{schema_version, SqliteConnection};
+#[cfg(feature = "rusqlite")]
+pub use native::NativeStorage;
+pub use params::{Params, ToSql};
+pub use row::{ColumnError, FromRow, FromValue, Row};
+pub use spin_sqlite_connection_derive::FromRow;
+pub use storage::{Rows, Storage};
@@ -0,0 +1,298 @@
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
+use rusqlite::types::ValueRef;
+use spin_sdk::sqlite::{Error, RowResult, Value};
+use std::sync::Mutex;
+
+use crate::storage::{Rows, Storage};
+
+fn error(error: impl ToString) -> Error {
+    Error::Io(error.to_string())
+}
+
+fn to_native(value: Value) -> rusqlite::types::Value {
+    match value {
+        Value::Integer(i) => rusqlite::types::Value::Integer(i),
+        Value::Real(r) => rusqlite::types::Value::Real(r),
+        Value::Text(s) => rusqlite::types::Value::Text(s),
+        Value::Blob(b) => rusqlite::types::Value::Blob(b),
+        Value::Null => rusqlite::types::Value::Null,
+    }
+}
+
+fn from_native(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Integer(i) => Value::Integer(i),
+        ValueRef::Real(r) => Value::Real(r),
+        ValueRef::Text(s) => Value::Text(String::from_utf8_lossy(s).into_owned()),
+        ValueRef::Blob(b) => Value::Blob(b.to_vec()),
+        ValueRef::Null => Value::Null,
+    }
+}
+
+/// A sqlite database outside the Spin host, to run the statements of a
+/// [`SqliteConnection`](crate::SqliteConnection) in native tests.
+///
+/// All rows are read before the first one is returned.
+pub struct NativeStorage(Mutex<rusqlite::Connection>);
+
+impl NativeStorage {
+    pub fn new(connection: rusqlite::Connection) -> Self {
+        Self(Mutex::new(connection))
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        rusqlite::Connection::open_in_memory()
+            .map(Self::new)
+            .map_err(error)
+    }
+
+    fn rows(&self, sql: &str, parameters: Vec<Value>) -> Result<(Vec<String>, Rows), Error> {
+        let connection = self.0.lock().map_err(error)?;
+        let mut statement = connection.prepare(sql).map_err(error)?;
+        let columns = statement
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let count = columns.len();
+        let rows = statement
+            .query(rusqlite::params_from_iter(
+                parameters.into_iter().map(to_native),
+            ))
+            .map_err(error)?
+            .mapped(|row| {
+                (0..count)
+                    .map(|i| row.get_ref(i).map(from_native))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map(|values| values.map(|values| RowResult { values }).map_err(error))
+            .collect::<Vec<_>>();
+        Ok((columns, stream::iter(rows).boxed()))
+    }
+}
+
+impl Storage for NativeStorage {
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        parameters: Vec<Value>,
+    ) -> BoxFuture<'a, Result<(Vec<String>, Rows), Error>> {
+        futures::future::ready(self.rows(sql, parameters)).boxed()
+    }
+
+    fn changes(&self) -> BoxFuture<'_, u64> {
+        let changes = self
+            .0
+            .lock()
+            .map(|connection| connection.changes())
+            .unwrap_or_default();
+        futures::future::ready(changes).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use spin_sdk::sqlite::Error;
+    use std::sync::{Arc, Mutex};
+
+    use super::NativeStorage;
+    use crate::{params, schema_version, FromRow, SqliteConnection};
+
+    #[derive(FromRow)]
+    struct Tag {
+        name: String,
+        count: Option<i64>,
+    }
+
+    #[test]
+    fn statements_run_natively() {
+        block_on(async {
+            let connection = SqliteConnection::<Error>::try_open(
+                NativeStorage::open_in_memory().unwrap(),
+                Some("CREATE TABLE tag (name TEXT NOT NULL, count INTEGER);"),
+            )
+            .await
+            .unwrap();
+            let inserted = connection
+                .execute(
+                    "INSERT INTO tag (name, count) VALUES (?, ?), (?, ?)",
+                    params!["kerst", 3, "pasen", None::<i64>],
+                )
+                .await
+                .unwrap();
+            assert_eq!(inserted, 2);
+            let tags = connection
+                .query::<Tag>("SELECT name, count FROM tag ORDER BY name", params![])
+                .await
+                .unwrap();
+            assert_eq!(
+                tags.iter()
+                    .map(|tag| (tag.name.as_str(), tag.count))
+                    .collect::<Vec<_>>(),
+                vec![("kerst", Some(3)), ("pasen", None)]
+            );
+            assert!(connection
+                .execute("INSERT INTO missing VALUES (1)", params![])
+                .await
+                .is_err());
+            assert!(connection
+                .execute("INSERT INTO tag (name) VALUES (NULL)", params![])
+                .await
+                .is_err());
+        });
+    }
+
+    #[derive(FromRow)]
+    struct Version {
+        user_version: usize,
+    }
+
+    #[test]
+    fn failed_upgrade_keeps_version() {
+        block_on(async {
+            let connection =
+                SqliteConnection::<Error>::try_open(NativeStorage::open_in_memory().unwrap(), None)
+                    .await
+                    .unwrap();
+            let upgrades = "CREATE TABLE tag (name TEXT NOT NULL);\nALTER TABLE missing ADD COLUMN count INTEGER;";
+            assert!(connection.upgrade(upgrades).await.is_err());
+            let version = connection
+                .query::<Version>("PRAGMA user_version", params![])
+                .await
+                .unwrap();
+            assert_eq!(version[0].user_version, 1);
+            assert!(connection.upgrade(upgrades).await.is_err());
+            assert!(connection
+                .execute("INSERT INTO tag (name) VALUES ('kerst')", params![])
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn upgrade_statements_span_lines() {
+        block_on(async {
+            let connection =
+                SqliteConnection::<Error>::try_open(NativeStorage::open_in_memory().unwrap(), None)
+                    .await
+                    .unwrap();
+            let upgrades = "-- tags; one per name\nCREATE TABLE tag (\n  name TEXT NOT NULL\n);\n\nCREATE UNIQUE INDEX tag_name ON tag (name);\n";
+            assert_eq!(schema_version(upgrades), 2);
+            connection.upgrade(upgrades).await.unwrap();
+            let version = connection
+                .query::<Version>("PRAGMA user_version", params![])
+                .await
+                .unwrap();
+            assert_eq!(version[0].user_version, 2);
+            assert!(connection
+                .execute(
+                    "INSERT INTO tag (name) VALUES ('kerst'), ('kerst')",
+                    params![]
+                )
+                .await
+                .is_err());
+        });
+    }
+
+    #[derive(FromRow)]
+    struct Count {
+        count: i64,
+    }
+
+    async fn count(connection: &SqliteConnection<Error>, table: &str) -> i64 {
+        connection
+            .query::<Count>(format!("SELECT COUNT(*) AS count FROM {table}"), params![])
+            .await
+            .unwrap()[0]
+            .count
+    }
+
+    #[test]
+    fn transactions_and_savepoints() {
+        block_on(async {
+            let failures = Arc::new(Mutex::new(vec![]));
+            let hook = failures.clone();
+            let connection = SqliteConnection::<Error>::try_open(
+                NativeStorage::open_in_memory().unwrap(),
+                Some("PRAGMA foreign_keys = ON;\nCREATE TABLE lyric (id TEXT PRIMARY KEY);\nCREATE TABLE member (lyric_id TEXT REFERENCES lyric(id) DEFERRABLE INITIALLY DEFERRED);"),
+            )
+            .await
+            .unwrap()
+            .with_rollback_failure(move |error, rollback| {
+                hook.lock()
+                    .unwrap()
+                    .push(format!("{error:?} {rollback:?}"))
+            });
+
+            let committed = connection
+                .transaction(async |connection| {
+                    connection
+                        .execute("INSERT INTO lyric (id) VALUES ('a')", params![])
+                        .await?;
+                    let inner = connection
+                        .transaction(async |connection| {
+                            connection
+                                .execute("INSERT INTO lyric (id) VALUES ('b')", params![])
+                                .await?;
+                            connection
+                                .execute("INSERT INTO missing VALUES (1)", params![])
+                                .await
+                        })
+                        .await;
+                    assert!(inner.is_err());
+                    Ok(())
+                })
+                .await;
+            assert!(committed.is_ok());
+            assert_eq!(count(&connection, "lyric").await, 1);
+
+            let rolled_back = connection
+                .transaction(async |connection| {
+                    connection
+                        .execute("INSERT INTO lyric (id) VALUES ('c')", params![])
+                        .await?;
+                    connection
+                        .execute("INSERT INTO lyric (id) VALUES ('a')", params![])
+                        .await
+                })
+                .await;
+            assert!(rolled_back.is_err());
+            assert_eq!(count(&connection, "lyric").await, 1);
+
+            let commit_failed = connection
+                .transaction(async |connection| {
+                    connection
+                        .execute("INSERT INTO member (lyric_id) VALUES ('z')", params![])
+                        .await
+                })
+                .await;
+            assert!(commit_failed.is_err());
+            assert_eq!(count(&connection, "member").await, 0);
+            assert!(connection
+                .transaction(async |connection| {
+                    connection
+                        .execute("INSERT INTO member (lyric_id) VALUES ('a')", params![])
+                        .await
+                })
+                .await
+                .is_ok());
+            assert_eq!(count(&connection, "member").await, 1);
+            assert!(failures.lock().unwrap().is_empty());
+
+            let rollback_failed = connection
+                .transaction(async |connection| {
+                    connection.execute("ROLLBACK", params![]).await?;
+                    connection
+                        .execute("INSERT INTO missing VALUES (1)", params![])
+                        .await
+                })
+                .await;
+            assert!(rollback_failed.is_err());
+            let failures = failures.lock().unwrap();
+            assert_eq!(failures.len(), 1);
+            assert!(failures[0].contains("missing"));
+            assert!(failures[0].contains("no transaction is active"));
+        });
+    }
+}
@@ -0,0 +1,236 @@
+use spin_sdk::sqlite::{Error, Value};
+
+/// Conversion to a sqlite value for binding a parameter.
+///
+/// Implemented for the integer types that fit in an `i64` without loss,
+/// so an unsigned 64 bit value has to be converted explicitly.
+pub trait ToSql {
+    fn to_sql(&self) -> Value;
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToSql for str {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.to_owned())
+    }
+}
+
+impl ToSql for String {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+impl ToSql for [u8] {
+    fn to_sql(&self) -> Value {
+        Value::Blob(self.to_vec())
+    }
+}
+
+impl ToSql for Vec<u8> {
+    fn to_sql(&self) -> Value {
+        Value::Blob(self.clone())
+    }
+}
+
+impl ToSql for f64 {
+    fn to_sql(&self) -> Value {
+        Value::Real(*self)
+    }
+}
+
+impl ToSql for bool {
+    fn to_sql(&self) -> Value {
+        Value::Integer(i64::from(*self))
+    }
+}
+
+macro_rules! integer_to_sql {
+    ($($t:ty),*) => {
+        $(impl ToSql for $t {
+            fn to_sql(&self) -> Value {
+                Value::Integer(i64::from(*self))
+            }
+        })*
+    };
+}
+
+integer_to_sql!(u8, u16, u32, i8, i16, i32, i64);
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn to_sql(&self) -> Value {
+        self.as_ref().map(ToSql::to_sql).unwrap_or(Value::Null)
+    }
+}
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    fn to_sql(&self) -> Value {
+        (**self).to_sql()
+    }
+}
+
+/// Stored as text in the same format as `strftime('%Y-%m-%dT%H:%M:%fZ', 'now')`
+#[cfg(feature = "chrono")]
+impl ToSql for chrono::DateTime<chrono::Utc> {
+    fn to_sql(&self) -> Value {
+        Value::Text(self.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+    }
+}
+
+/// The parameters of a statement, usually made with [`params!`](crate::params)
+/// or [`named_params!`](crate::named_params)
+#[derive(Clone, Debug, Default)]
+pub enum Params {
+    #[default]
+    None,
+    /// Bound in order to the `?` parameters
+    Positional(Vec<Value>),
+    /// Bound by name to the `:name` parameters, in any order
+    Named(Vec<(&'static str, Value)>),
+}
+
+impl From<Vec<Value>> for Params {
+    fn from(values: Vec<Value>) -> Self {
+        Self::Positional(values)
+    }
+}
+
+impl Params {
+    /// The values in the order sqlite numbers the parameters of `sql`
+    pub(crate) fn bind(self, sql: &str) -> Result<Vec<Value>, Error> {
+        match self {
+            Self::None => Ok(vec![]),
+            Self::Positional(values) => Ok(values),
+            Self::Named(values) => parameter_names(sql)
+                .into_iter()
+                .map(|name| {
+                    values
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, value)| value.clone())
+                        .ok_or_else(|| Error::Io(format!("no value for parameter {name}")))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Sqlite numbers named parameters in order of their first appearance
+fn parameter_names(sql: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut quote = None;
+    for (start, c) in sql.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ':') => {
+                let name_end = sql[start + 1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map(|end| start + 1 + end)
+                    .unwrap_or(sql.len());
+                let name = &sql[start..name_end];
+                if name.len() > 1 && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Positional parameters, converted with [`ToSql`]
+///
+/// `params![id, title]`
+#[macro_export]
+macro_rules! params {
+    () => {
+        $crate::Params::None
+    };
+    ($($value:expr),+ $(,)?) => {
+        $crate::Params::Positional(::std::vec![$($crate::ToSql::to_sql(&$value)),+])
+    };
+}
+
+/// Named parameters, converted with [`ToSql`]
+///
+/// `named_params! { ":id": id, ":title": title }`
+#[macro_export]
+macro_rules! named_params {
+    ($($name:literal : $value:expr),* $(,)?) => {
+        $crate::Params::Named(::std::vec![$(($name, $crate::ToSql::to_sql(&$value))),*])
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use spin_sdk::sqlite::{Error, Value};
+
+    use super::{parameter_names, Params, ToSql};
+
+    /// Value does not implement PartialEq
+    fn debug(values: &[Value]) -> String {
+        format!("{values:?}")
+    }
+
+    #[test]
+    fn values() {
+        let title = "Sofietje".to_owned();
+        let missing: Option<u32> = None;
+        let Params::Positional(values) = params![title, "a", 3u32, true, missing, Some(1.5)] else {
+            panic!("positional expected");
+        };
+        assert_eq!(
+            debug(&values),
+            debug(&[
+                Value::Text("Sofietje".to_owned()),
+                Value::Text("a".to_owned()),
+                Value::Integer(3),
+                Value::Integer(1),
+                Value::Null,
+                Value::Real(1.5),
+            ])
+        );
+        assert_eq!(
+            debug(&[b"ab".as_slice().to_sql()]),
+            debug(&[Value::Blob(b"ab".to_vec())])
+        );
+    }
+
+    #[test]
+    fn names_in_order_of_appearance() {
+        assert_eq!(
+            parameter_names(
+                "UPDATE lyric SET title = :title, modified = strftime('%H:%M', 'now') WHERE id = :id AND title <> :title"
+            ),
+            vec![":title", ":id"]
+        );
+    }
+
+    #[test]
+    fn named_are_bound_by_name() {
+        let params = named_params! { ":id": "a", ":title": "Sofietje" };
+        assert_eq!(
+            debug(
+                &params
+                    .clone()
+                    .bind("UPDATE lyric SET title = :title WHERE id = :id")
+                    .unwrap()
+            ),
+            debug(&[
+                Value::Text("Sofietje".to_owned()),
+                Value::Text("a".to_owned())
+            ])
+        );
+        assert!(matches!(
+            params.bind("SELECT :missing"),
+            Err(Error::Io(message)) if message.contains(":missing")
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+use spin_sdk::sqlite::{Error, RowResult, Value};
+use std::fmt::{Display, Formatter};
+
+/// A row of a query result, with its values looked up by column name
+pub struct Row<'a> {
+    columns: &'a [String],
+    values: &'a [Value],
+}
+
+impl<'a> Row<'a> {
+    pub fn new(columns: &'a [String], row: &'a RowResult) -> Self {
+        Self {
+            columns,
+            values: &row.values,
+        }
+    }
+
+    pub fn value(&self, column: &str) -> Result<&'a Value, ColumnError> {
+        self.columns
+            .iter()
+            .position(|name| name == column)
+            .and_then(|index| self.values.get(index))
+            .ok_or_else(|| ColumnError::Missing(column.to_owned()))
+    }
+
+    /// The value of the column converted to `T`, null only converts to an `Option`
+    pub fn get<T: FromValue>(&self, column: &str) -> Result<T, ColumnError> {
+        T::from_value(self.value(column)?).ok_or_else(|| ColumnError::Type {
+            column: column.to_owned(),
+            expected: std::any::type_name::<T>(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColumnError {
+    /// The query has no column with this name
+    Missing(String),
+    /// The value in the column could not be converted
+    Type {
+        column: String,
+        expected: &'static str,
+    },
+}
+
+impl Display for ColumnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(column) => write!(f, "missing column {column}"),
+            Self::Type { column, expected } => {
+                write!(f, "value in column {column} is not a {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ColumnError {}
+
+/// So a connection with the plain sqlite error can decode rows as well
+impl From<ColumnError> for Error {
+    fn from(error: ColumnError) -> Self {
+        Error::Io(error.to_string())
+    }
+}
+
+/// Conversion from a single sqlite value
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+/// Conversion from a row, usually derived with `#[derive(FromRow)]`
+pub trait FromRow: Sized {
+    type Error: From<ColumnError>;
+
+    fn from_row(row: &Row<'_>) -> Result<Self, Self::Error>;
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        <&str>::try_from(value).ok().map(String::from)
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: &Value) -> Option<Self> {
+        <&[u8]>::try_from(value).ok().map(<[u8]>::to_vec)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Real(f) => Some(*f),
+            Value::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        value.try_into().ok()
+    }
+}
+
+macro_rules! integer_from_value {
+    ($($t:ty),*) => {
+        $(impl FromValue for $t {
+            fn from_value(value: &Value) -> Option<Self> {
+                value.try_into().ok()
+            }
+        })*
+    };
+}
+
+integer_from_value!(u8, u16, u32, u64, i8, i16, i32, i64, usize, isize);
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromValue for chrono::DateTime<chrono::Utc> {
+    fn from_value(value: &Value) -> Option<Self> {
+        <&str>::try_from(value).ok()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spin_sdk::sqlite::{RowResult, Value};
+
+    use super::{ColumnError, FromRow, Row};
+    use crate as spin_sqlite_connection;
+
+    #[derive(Debug, PartialEq, spin_sqlite_connection::FromRow)]
+    struct User {
+        id: String,
+        #[row(column = "user_name")]
+        name: String,
+        age: Option<u32>,
+        #[row(column = "role", with = "admin")]
+        admin: bool,
+        #[row(flatten)]
+        score: Score,
+        #[row(skip)]
+        password: String,
+    }
+
+    #[derive(Debug, PartialEq, spin_sqlite_connection::FromRow)]
+    struct Score(#[row(column = "score")] f64);
+
+    fn admin(role: String) -> Result<bool, ColumnError> {
+        Ok(role == "admin")
+    }
+
+    fn columns() -> Vec<String> {
+        ["id", "user_name", "age", "role", "score"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn row(values: Vec<Value>) -> RowResult {
+        RowResult { values }
+    }
+
+    #[test]
+    fn derived() {
+        let columns = columns();
+        let row = row(vec![
+            Value::Text("a".to_owned()),
+            Value::Text("paul".to_owned()),
+            Value::Null,
+            Value::Text("admin".to_owned()),
+            Value::Real(1.5),
+        ]);
+        assert_eq!(
+            User::from_row(&Row::new(&columns, &row)).unwrap(),
+            User {
+                id: "a".to_owned(),
+                name: "paul".to_owned(),
+                age: None,
+                admin: true,
+                score: Score(1.5),
+                password: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn typed_getters() {
+        let columns = columns();
+        let row = row(vec![
+            Value::Text("a".to_owned()),
+            Value::Blob(b"paul".to_vec()),
+            Value::Integer(52),
+            Value::Null,
+            Value::Integer(2),
+        ]);
+        let row = Row::new(&columns, &row);
+        assert_eq!(row.get::<String>("user_name").unwrap(), "paul");
+        assert_eq!(row.get::<Vec<u8>>("user_name").unwrap(), b"paul");
+        assert_eq!(row.get::<u8>("age").unwrap(), 52);
+        assert_eq!(row.get::<Option<i64>>("age").unwrap(), Some(52));
+        assert_eq!(row.get::<Option<String>>("role").unwrap(), None);
+        assert_eq!(row.get::<f64>("score").unwrap(), 2.0);
+        assert_eq!(
+            row.get::<String>("role"),
+            Err(ColumnError::Type {
+                column: "role".to_owned(),
+                expected: "alloc::string::String"
+            })
+        );
+        assert_eq!(
+            row.get::<String>("email"),
+            Err(ColumnError::Missing("email".to_owned()))
+        );
+    }
+}
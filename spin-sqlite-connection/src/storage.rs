@@ -0,0 +1,63 @@
+use futures::{future::BoxFuture, stream, stream::BoxStream, FutureExt, StreamExt};
+use spin_sdk::sqlite::{Connection, Error, RowResult, Value};
+use std::sync::Arc;
+
+/// The rows of a statement as they are read, an error of the statement as a whole is the last item
+pub type Rows = BoxStream<'static, Result<RowResult, Error>>;
+
+/// Runs the statements of a [`SqliteConnection`](crate::SqliteConnection).
+///
+/// Implemented for the connection of the Spin host and, with the `rusqlite` feature,
+/// for [`NativeStorage`](crate::NativeStorage) so the same statements run outside a Spin host.
+pub trait Storage: Send + Sync {
+    /// The column names and the rows of `sql`, with the parameters in the order sqlite numbers them
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        parameters: Vec<Value>,
+    ) -> BoxFuture<'a, Result<(Vec<String>, Rows), Error>>;
+
+    /// The number of rows changed by the last insert, update or delete
+    fn changes(&self) -> BoxFuture<'_, u64>;
+}
+
+impl Storage for Connection {
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        parameters: Vec<Value>,
+    ) -> BoxFuture<'a, Result<(Vec<String>, Rows), Error>> {
+        async move {
+            let query_result = self.execute(sql, parameters).await?;
+            let columns = query_result.columns().to_vec();
+            let rows = stream::unfold(Some(query_result), |state| async move {
+                let mut query_result = state?;
+                match query_result.next().await {
+                    Some(row) => Some((Ok(row), Some(query_result))),
+                    None => query_result.result().await.err().map(|e| (Err(e), None)),
+                }
+            });
+            Ok((columns, rows.boxed()))
+        }
+        .boxed()
+    }
+
+    fn changes(&self) -> BoxFuture<'_, u64> {
+        Connection::changes(self).boxed()
+    }
+}
+
+/// Storage shared by the connections of several requests
+impl<T: Storage + ?Sized> Storage for Arc<T> {
+    fn query<'a>(
+        &'a self,
+        sql: &'a str,
+        parameters: Vec<Value>,
+    ) -> BoxFuture<'a, Result<(Vec<String>, Rows), Error>> {
+        (**self).query(sql, parameters)
+    }
+
+    fn changes(&self) -> BoxFuture<'_, u64> {
+        (**self).changes()
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The statements of a transaction at some nesting level.
+/// The outermost transaction is a real transaction, the nested ones are savepoints.
+pub(crate) struct Level<'a> {
+    depth: usize,
+    open: &'a AtomicUsize,
+}
+
+impl<'a> Level<'a> {
+    /// Enters a level, it is left again when the level is dropped
+    pub(crate) fn enter(open: &'a AtomicUsize) -> Self {
+        Self {
+            depth: open.fetch_add(1, Ordering::SeqCst),
+            open,
+        }
+    }
+
+    pub(crate) fn begin(&self) -> Vec<String> {
+        match self.depth {
+            0 => vec!["BEGIN TRANSACTION".to_owned()],
+            depth => vec![format!("SAVEPOINT level_{depth}")],
+        }
+    }
+
+    pub(crate) fn commit(&self) -> Vec<String> {
+        match self.depth {
+            0 => vec!["COMMIT".to_owned()],
+            depth => vec![format!("RELEASE SAVEPOINT level_{depth}")],
+        }
+    }
+
+    /// A savepoint stays on the stack after rolling back to it, so it is released as well
+    pub(crate) fn roll_back(&self) -> Vec<String> {
+        match self.depth {
+            0 => vec!["ROLLBACK".to_owned()],
+            depth => vec![
+                format!("ROLLBACK TO SAVEPOINT level_{depth}"),
+                format!("RELEASE SAVEPOINT level_{depth}"),
+            ],
+        }
+    }
+}
+
+impl Drop for Level<'_> {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::Level;
+
+    #[test]
+    fn nested_levels_are_savepoints() {
+        let open = AtomicUsize::new(0);
+        let outer = Level::enter(&open);
+        assert_eq!(outer.begin(), vec!["BEGIN TRANSACTION"]);
+        {
+            let inner = Level::enter(&open);
+            assert_eq!(inner.begin(), vec!["SAVEPOINT level_1"]);
+            assert_eq!(inner.commit(), vec!["RELEASE SAVEPOINT level_1"]);
+            assert_eq!(
+                inner.roll_back(),
+                vec!["ROLLBACK TO SAVEPOINT level_1", "RELEASE SAVEPOINT level_1"]
+            );
+        }
+        assert_eq!(open.load(Ordering::SeqCst), 1);
+        assert_eq!(Level::enter(&open).begin(), vec!["SAVEPOINT level_1"]);
+        assert_eq!(outer.commit(), vec!["COMMIT"]);
+        assert_eq!(outer.roll_back(), vec!["ROLLBACK"]);
+        drop(outer);
+        assert_eq!(open.load(Ordering::SeqCst), 0);
+    }
+}
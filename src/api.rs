@@ -1,28 +1,891 @@
-use crate::handler;
+use crate::{
+    Result,
+    context::{Context, X_REQUEST_ID},
+    handler, message,
+    persistence::Database,
+};
 use axum::{
-    Router,
-    routing::{delete, get, post, put},
+    Extension, Router,
+    extract::{Request, State},
+    http::{HeaderValue, Uri, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, patch, post, put},
+};
+use model::{
+    Tenant,
+    error::{AuthenticationError, Error},
+    response::{basic_credentials, constant_time_eq},
 };
-use tower_http::auth::AddAuthorizationLayer;
 
-pub fn create_router(username: &str, password: &str) -> Router {
-    Router::new()
+const TENANT_PREFIX: &str = "/lipl/t/";
+
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+fn request_credentials(
+    request: &Request,
+) -> std::result::Result<(String, String), AuthenticationError> {
+    basic_credentials(
+        request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok()),
+    )
+}
+
+/// Requests without the basic credentials of the api are answered with 401 Unauthorized,
+/// requests for a tenant need the credentials of that tenant instead
+async fn authorize(
+    State(credentials): State<Credentials>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    let credentials = match request.extensions().get::<Tenant>() {
+        Some(tenant) => Credentials {
+            username: tenant.username.clone(),
+            password: tenant.password.clone(),
+        },
+        None => credentials,
+    };
+    authenticate(&credentials, request, next).await
+}
+
+/// The tenants are managed with the admin credentials, whatever tenant the request is for
+async fn authorize_admin(
+    State(admin): State<Credentials>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    authenticate(&admin, request, next).await
+}
+
+/// Empty credentials never match, like those of a tenant added before tenants had credentials
+async fn authenticate(
+    credentials: &Credentials,
+    mut request: Request,
+    next: Next,
+) -> Result<Response> {
+    let (username, password) = request_credentials(&request)?;
+    let username_matches =
+        constant_time_eq(&username, &credentials.username) && !credentials.username.is_empty();
+    let password_matches =
+        constant_time_eq(&password, &credentials.password) && !credentials.password.is_empty();
+    if !username_matches {
+        return Err(AuthenticationError::Username.into());
+    }
+    if !password_matches {
+        return Err(AuthenticationError::Password.into());
+    }
+    let context = request
+        .extensions_mut()
+        .get_mut::<Context>()
+        .map(|context| {
+            message::user_authenticated(context, &username);
+            context.user = Some(username);
+            context.clone()
+        });
+    let mut response = next.run(request).await;
+    // Handed back so the response is logged with the user
+    if let Some(context) = context {
+        response.extensions_mut().insert(context);
+    }
+    Ok(response)
+}
+
+/// Adds a context to requests that do not have one yet, and sends its id back in the `X-Request-Id` header
+async fn context(mut request: Request, next: Next) -> Response {
+    let context = match request.extensions().get::<Context>() {
+        Some(context) => context.clone(),
+        None => Context::new(
+            request
+                .headers()
+                .get(X_REQUEST_ID)
+                .and_then(|h| h.to_str().ok()),
+        ),
+    };
+    request.extensions_mut().insert(context.clone());
+    let mut response = next.run(request).await;
+    if let Ok(id) = HeaderValue::from_str(&context.id) {
+        response.headers_mut().insert(X_REQUEST_ID, id);
+    }
+    response
+}
+
+/// Splits `/lipl/t/{tenant}/api/...` in the tenant and the uri without the prefix, `/lipl/api/...`
+fn strip_tenant(uri: &Uri) -> Option<(String, Uri)> {
+    let (tenant, path) = uri.path().strip_prefix(TENANT_PREFIX)?.split_once('/')?;
+    let path_and_query = match uri.query() {
+        Some(query) => format!("/lipl/{path}?{query}"),
+        None => format!("/lipl/{path}"),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Some((tenant.to_owned(), Uri::from_parts(parts).ok()?))
+}
+
+fn host(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.split(':').next())
+}
+
+/// Adds the tenant and its database to the request, found by the `/lipl/t/{tenant}` path prefix,
+/// which is removed before routing, or else by host name.
+/// Requests for no tenant in particular use `database` itself.
+/// Requests without credentials are unauthorized before any tenant is looked up,
+/// requests for an unknown tenant are not found.
+async fn tenant(
+    State(database): State<Database>,
+    Extension(context): Extension<Context>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response> {
+    request_credentials(&request)?;
+    let tenant = match strip_tenant(request.uri()) {
+        Some((name, uri)) => {
+            *request.uri_mut() = uri;
+            let connection = database.open(&context).await?;
+            Some(
+                connection
+                    .select_tenant_by_name(&name)
+                    .await?
+                    .ok_or(Error::NotFound)?,
+            )
+        }
+        None => match host(&request) {
+            Some(host) => database.select_tenant_by_host(&context, host).await?,
+            None => None,
+        },
+    };
+    let database = match &tenant {
+        Some(tenant) => database.for_tenant(&tenant.name)?,
+        None => database,
+    };
+    request.extensions_mut().insert(database);
+    if let Some(tenant) = tenant {
+        request.extensions_mut().insert(tenant);
+    }
+    Ok(next.run(request).await)
+}
+
+/// The tenants are managed with the `admin` credentials,
+/// without them there are no tenant routes and no tenants are looked up
+pub fn create_router(
+    database: Database,
+    credentials: Credentials,
+    admin: Option<Credentials>,
+) -> Router {
+    let api = Router::new()
         .route("/lipl/api/v1/lyric", get(handler::get_lyric_list))
         .route("/lipl/api/v1/lyric/{id}", get(handler::get_lyric))
         .route("/lipl/api/v1/lyric", post(handler::insert_lyric))
+        .route(
+            "/lipl/api/v1/lyric/import/chordpro",
+            post(handler::import_chordpro),
+        )
+        .route(
+            "/lipl/api/v1/lyric/import/openlyrics",
+            post(handler::import_openlyrics),
+        )
         .route("/lipl/api/v1/lyric/{id}", put(handler::update_lyric))
+        .route("/lipl/api/v1/lyric/{id}", patch(handler::patch_lyric))
         .route("/lipl/api/v1/lyric/{id}", delete(handler::delete_lyric))
         .route("/lipl/api/v1/playlist", get(handler::get_playlist_list))
         .route("/lipl/api/v1/playlist/{id}", get(handler::get_playlist))
         .route("/lipl/api/v1/playlist", post(handler::insert_playlist))
         .route("/lipl/api/v1/playlist/{id}", put(handler::update_playlist))
+        .route("/lipl/api/v1/playlist/{id}", patch(handler::patch_playlist))
         .route(
             "/lipl/api/v1/playlist/{id}",
             delete(handler::delete_playlist),
         )
+        .route(
+            "/lipl/api/v1/playlist/{id}/export.cho",
+            get(handler::export_playlist_chordpro),
+        )
+        .route(
+            "/lipl/api/v1/playlist/{id}/members",
+            post(handler::insert_member),
+        )
+        .route(
+            "/lipl/api/v1/playlist/{id}/members",
+            patch(handler::move_members),
+        )
+        .route(
+            "/lipl/api/v1/playlist/{id}/members/{position}",
+            delete(handler::delete_member),
+        )
+        .route("/lipl/api/v1/tag", get(handler::get_tag_list))
+        .route("/lipl/api/v1/tag/{id}", get(handler::get_tag))
+        .route("/lipl/api/v1/tag", post(handler::insert_tag))
+        .route("/lipl/api/v1/tag/{id}", put(handler::update_tag))
+        .route("/lipl/api/v1/tag/{id}", delete(handler::delete_tag))
         .route("/lipl/api/v1/db", get(handler::get_db))
         .route("/lipl/api/v1/db", post(handler::replace_db))
+        .route("/lipl/api/v1/db/archive", get(handler::get_db_archive))
+        .route("/lipl/api/v1/db/archive", post(handler::restore_db_archive))
+        .route(
+            "/lipl/api/v1/db/openlyrics",
+            get(handler::get_db_openlyrics),
+        )
         .route("/lipl/api/v1/uuid/{id}", get(handler::get_uuid))
         .route("/lipl/api/v1/user", get(handler::get_user_list))
-        .layer(AddAuthorizationLayer::basic(username, password))
+        .layer(middleware::from_fn_with_state(credentials, authorize));
+    let Some(admin) = admin else {
+        return api
+            .layer(Extension(database))
+            .layer(middleware::from_fn(context));
+    };
+    let api = api.merge(
+        Router::new()
+            .route("/lipl/api/v1/tenant", get(handler::get_tenant_list))
+            .route("/lipl/api/v1/tenant", post(handler::insert_tenant))
+            .route("/lipl/api/v1/tenant/{name}", delete(handler::delete_tenant))
+            .layer(middleware::from_fn_with_state(admin, authorize_admin))
+            .with_state(database.clone()),
+    );
+    // A fallback so the tenant prefix is removed before the api routes are matched
+    Router::new()
+        .fallback_service(api)
+        .layer(middleware::from_fn_with_state(database, tenant))
+        .layer(middleware::from_fn(context))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Request, StatusCode, header, request::Builder},
+        response::Response,
+    };
+    use futures::executor::block_on;
+    use model::{Db, Lyric, Playlist, Tag, Uuid};
+    use serde::{Serialize, de::DeserializeOwned};
+    use spin_sqlite_connection::NativeStorage;
+    use tower::ServiceExt;
+
+    use super::{Credentials, create_router};
+    use crate::{
+        context::Context,
+        persistence::{Database, MemoryStore},
+    };
+
+    /// paul:password, the user added by the migrations
+    const AUTHORIZATION: &str = "Basic cGF1bDpwYXNzd29yZA==";
+    const API: &str = "/lipl/api/v1";
+
+    /// admin:secret, who manages the tenants
+    const ADMIN_AUTHORIZATION: &str = "Basic YWRtaW46c2VjcmV0";
+
+    fn credentials(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    fn create(database: Database) -> Router {
+        create_router(
+            database,
+            credentials("paul", "password"),
+            Some(credentials("admin", "secret")),
+        )
+    }
+
+    fn router() -> Router {
+        create(Database::sqlite(NativeStorage::open_in_memory().unwrap()))
+    }
+
+    /// The same routes on sqlite and on the key-value store
+    fn routers() -> [Router; 2] {
+        [router(), key_value()]
+    }
+
+    /// A key-value store with the user of the app credentials, as the Spin component seeds it
+    fn key_value() -> Router {
+        let database = Database::key_value(MemoryStore::default());
+        block_on(database.seed_user(&Context::new(None), "paul", "password")).unwrap();
+        create(database)
+    }
+
+    fn request(method: &str, path: &str) -> Builder {
+        Request::builder()
+            .method(method)
+            .uri(format!("{API}{path}"))
+            .header(header::AUTHORIZATION, AUTHORIZATION)
+    }
+
+    fn send(router: &Router, request: Builder, body: Body) -> Response {
+        block_on(router.clone().oneshot(request.body(body).unwrap())).unwrap()
+    }
+
+    fn status(router: &Router, method: &str, path: &str) -> StatusCode {
+        send(router, request(method, path), Body::empty()).status()
+    }
+
+    fn send_json(router: &Router, method: &str, path: &str, body: &impl Serialize) -> StatusCode {
+        send(
+            router,
+            request(method, path).header(header::CONTENT_TYPE, "application/json"),
+            Body::from(serde_json::to_vec(body).unwrap()),
+        )
+        .status()
+    }
+
+    fn bytes(response: Response) -> Vec<u8> {
+        block_on(to_bytes(response.into_body(), usize::MAX))
+            .unwrap()
+            .to_vec()
+    }
+
+    fn get<T: DeserializeOwned>(router: &Router, path: &str) -> T {
+        let response = send(router, request("GET", path), Body::empty());
+        assert_eq!(response.status(), StatusCode::OK, "GET {path}");
+        serde_json::from_slice(&bytes(response)).unwrap()
+    }
+
+    fn lyric(title: &str) -> Lyric {
+        Lyric::new(
+            Uuid::default().to_string(),
+            title.to_owned(),
+            vec![vec!["Zij dronk ranja met een rietje".to_owned()]],
+        )
+    }
+
+    fn db() -> Db {
+        let lyrics = vec![lyric("Sofietje"), lyric("Dodenrit")];
+        Db {
+            playlists: vec![Playlist::new(
+                Uuid::default().to_string(),
+                "Kerst".to_owned(),
+                lyrics
+                    .iter()
+                    .map(|lyric| lyric.id.as_str().into())
+                    .collect(),
+            )],
+            lyrics,
+        }
+    }
+
+    #[test]
+    fn unauthorized() {
+        let router = router();
+        let path = format!("{API}/lyric");
+        let response = send(
+            &router,
+            Request::builder().uri(path.as_str()),
+            Body::empty(),
+        );
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+        let wrong_password = Request::builder()
+            .uri(path.as_str())
+            .header(header::AUTHORIZATION, "Basic cGF1bDp3cm9uZw==");
+        assert_eq!(
+            send(&router, wrong_password, Body::empty()).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        for router in routers() {
+            let users = get::<Vec<serde_json::Value>>(&router, "/user");
+            assert_eq!(users.len(), 1);
+            assert_eq!(users[0]["name"], "paul");
+        }
+    }
+
+    #[test]
+    fn request_id() {
+        let router = router();
+        let given = send(
+            &router,
+            request("GET", "/lyric").header("x-request-id", "abc-123"),
+            Body::empty(),
+        );
+        assert_eq!(given.headers()["x-request-id"], "abc-123");
+        let logged = given.extensions().get::<Context>().unwrap();
+        assert_eq!(logged.user.as_deref(), Some("paul"));
+        let unauthorized = send(
+            &router,
+            Request::builder().uri(format!("{API}/lyric")),
+            Body::empty(),
+        );
+        let generated = unauthorized.headers()["x-request-id"].to_str().unwrap();
+        assert!(generated.parse::<Uuid>().is_ok());
+        let other = send(&router, request("GET", "/lyric"), Body::empty());
+        assert_ne!(other.headers()["x-request-id"], generated);
+    }
+
+    #[test]
+    fn lyric_routes() {
+        for router in routers() {
+            let mut lyric = lyric("Sofietje");
+            let path = format!("/lyric/{}", lyric.id);
+            assert_eq!(status(&router, "GET", &path), StatusCode::NOT_FOUND);
+            assert_eq!(
+                send_json(&router, "POST", "/lyric", &lyric),
+                StatusCode::CREATED
+            );
+
+            let response = send(&router, request("GET", &path), Body::empty());
+            assert_eq!(response.status(), StatusCode::OK);
+            let etag = response.headers()[header::ETAG].clone();
+            let quoted = etag.to_str().unwrap();
+            assert!(quoted.len() > 2 && quoted.starts_with('"') && quoted.ends_with('"'));
+            let not_modified = request("GET", &path).header(header::IF_NONE_MATCH, etag);
+            assert_eq!(
+                send(&router, not_modified, Body::empty()).status(),
+                StatusCode::NOT_MODIFIED
+            );
+
+            "Sofietje 2".clone_into(&mut lyric.title);
+            lyric.tags = vec!["kerst".to_owned()];
+            lyric.meta.artist = Some("Drs. P".to_owned());
+            let vendor_json =
+                request("PUT", &path).header(header::CONTENT_TYPE, "application/vnd.lipl+json");
+            assert_eq!(
+                send(
+                    &router,
+                    vendor_json,
+                    Body::from(serde_json::to_vec(&lyric).unwrap())
+                )
+                .status(),
+                StatusCode::NO_CONTENT
+            );
+            let text = request("PUT", &path).header(header::CONTENT_TYPE, "text/plain");
+            assert_eq!(
+                send(&router, text, Body::from("Sofietje 2\n\nZij dronk ranja")).status(),
+                StatusCode::NO_CONTENT
+            );
+            let stored = get::<Lyric>(&router, &path);
+            assert_eq!(stored.parts, vec![vec!["Zij dronk ranja".to_owned()]]);
+            assert_eq!(stored.tags, lyric.tags);
+            assert_eq!(stored.meta.artist, lyric.meta.artist);
+            let merge_patch = request("PATCH", &path)
+                .header(header::CONTENT_TYPE, "application/merge-patch+json")
+                .header(header::IF_MATCH, "x");
+            assert_eq!(
+                send(
+                    &router,
+                    merge_patch,
+                    Body::from(r#"{"title":"Sofietje 3"}"#)
+                )
+                .status(),
+                StatusCode::PRECONDITION_FAILED
+            );
+            let lyrics = get::<Vec<Lyric>>(&router, "/lyric");
+            assert_eq!(lyrics.len(), 1);
+            assert_eq!(lyrics[0].title, "Sofietje 2");
+            assert_eq!(get::<Lyric>(&router, &path).title, "Sofietje 2");
+
+            let chordpro = send(
+                &router,
+                request("GET", &format!("{path}.cho")),
+                Body::empty(),
+            );
+            assert_eq!(chordpro.status(), StatusCode::OK);
+            assert!(
+                String::from_utf8(bytes(chordpro))
+                    .unwrap()
+                    .contains("{title: Sofietje 2}")
+            );
+            assert_eq!(
+                status(&router, "GET", &format!("{path}.xml")),
+                StatusCode::OK
+            );
+
+            lyric.meta.key = Some("G".to_owned());
+            let lyric = lyric.with_parts("[G]Zij dronk [D/F#]ranja".parse().unwrap());
+            assert_eq!(
+                send_json(&router, "PUT", &path, &lyric),
+                StatusCode::NO_CONTENT
+            );
+            let transposed = get::<Lyric>(&router, &format!("{path}?key=A&with=chords"));
+            assert_eq!(transposed.meta.key.as_deref(), Some("A"));
+            assert_eq!(transposed.to_parts().to_text(), "[A]Zij dronk [E/G#]ranja");
+            assert_eq!(
+                status(&router, "GET", &format!("{path}?key=H")),
+                StatusCode::BAD_REQUEST
+            );
+            assert_eq!(status(&router, "DELETE", &path), StatusCode::NO_CONTENT);
+            assert_eq!(status(&router, "GET", &path), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn import_routes() {
+        for router in routers() {
+            let imported = send(
+                &router,
+                request("POST", "/lyric/import/chordpro"),
+                Body::from("{title: Sofietje}\nZij dronk ranja\n"),
+            );
+            assert_eq!(imported.status(), StatusCode::CREATED);
+            let ids = serde_json::from_slice::<Vec<String>>(&bytes(imported)).unwrap();
+            assert_eq!(
+                get::<Lyric>(&router, &format!("/lyric/{}", ids[0])).title,
+                "Sofietje"
+            );
+            assert_eq!(
+                send(
+                    &router,
+                    request("POST", "/lyric/import/chordpro"),
+                    Body::empty()
+                )
+                .status(),
+                StatusCode::UNPROCESSABLE_ENTITY
+            );
+        }
+    }
+
+    #[test]
+    fn playlist_routes() {
+        for router in routers() {
+            let db = db();
+            for lyric in &db.lyrics {
+                assert_eq!(
+                    send_json(&router, "POST", "/lyric", lyric),
+                    StatusCode::CREATED
+                );
+            }
+            let playlist = &db.playlists[0];
+            let path = format!("/playlist/{}", playlist.id);
+            assert_eq!(status(&router, "GET", &path), StatusCode::NOT_FOUND);
+            assert_eq!(
+                send_json(&router, "POST", "/playlist", playlist),
+                StatusCode::CREATED
+            );
+            assert_eq!(get::<Playlist>(&router, &path).members, playlist.members);
+            let etag = send(&router, request("GET", &path), Body::empty()).headers()[header::ETAG]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let ids = send(
+                &router,
+                request("GET", &format!("{path}?members=ids")),
+                Body::empty(),
+            );
+            assert_eq!(ids.headers()[header::ETAG], etag.as_str());
+            let not_modified = request("GET", &path).header(header::IF_NONE_MATCH, etag);
+            assert_eq!(
+                send(&router, not_modified, Body::empty()).status(),
+                StatusCode::NOT_MODIFIED
+            );
+            assert_eq!(get::<Vec<Playlist>>(&router, "/playlist").len(), 1);
+            assert_eq!(
+                status(&router, "GET", &format!("{path}/export.cho")),
+                StatusCode::OK
+            );
+
+            let moves = [model::MemberMove { from: 0, to: 1 }];
+            assert_eq!(
+                send_json(&router, "PATCH", &format!("{path}/members"), &moves),
+                StatusCode::NO_CONTENT
+            );
+            assert_eq!(
+                get::<Playlist>(&router, &path).members[0],
+                playlist.members[1]
+            );
+            let moves = [model::MemberMove { from: 0, to: 9 }];
+            assert_eq!(
+                send_json(&router, "PATCH", &format!("{path}/members"), &moves),
+                StatusCode::UNPROCESSABLE_ENTITY
+            );
+            assert_eq!(
+                status(&router, "DELETE", &format!("{path}/members/1")),
+                StatusCode::NO_CONTENT
+            );
+            assert_eq!(
+                status(&router, "DELETE", &format!("{path}/members/1")),
+                StatusCode::NOT_FOUND
+            );
+            let member = serde_json::json!({ "lyric_id": db.lyrics[0].id });
+            assert_eq!(
+                send_json(&router, "POST", &format!("{path}/members"), &member),
+                StatusCode::CREATED
+            );
+            assert_eq!(
+                send_json(&router, "POST", "/playlist/unknown/members", &member),
+                StatusCode::NOT_FOUND
+            );
+            assert_eq!(
+                send_json(&router, "PUT", &path, playlist),
+                StatusCode::NO_CONTENT
+            );
+            let etag = |router: &Router| {
+                send(router, request("GET", &path), Body::empty()).headers()[header::ETAG].clone()
+            };
+            let before = etag(&router);
+            let lyric = format!("/lyric/{}", db.lyrics[0].id);
+            assert_eq!(status(&router, "DELETE", &lyric), StatusCode::NO_CONTENT);
+            assert_ne!(etag(&router), before);
+            assert_eq!(get::<Playlist>(&router, &path).members.len(), 1);
+            assert_eq!(status(&router, "DELETE", &path), StatusCode::NO_CONTENT);
+            assert_eq!(status(&router, "GET", &path), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn tag_routes() {
+        for router in routers() {
+            let tag = Tag {
+                id: Uuid::default().to_string(),
+                name: "kerst".to_owned(),
+                count: 0,
+            };
+            let path = format!("/tag/{}", tag.id);
+            assert_eq!(
+                send_json(&router, "POST", "/tag", &tag),
+                StatusCode::CREATED
+            );
+            assert_eq!(get::<Vec<Tag>>(&router, "/tag"), vec![tag.clone()]);
+            let renamed = serde_json::json!({ "name": "pasen" });
+            assert_eq!(
+                send_json(&router, "PUT", &path, &renamed),
+                StatusCode::NO_CONTENT
+            );
+            assert_eq!(get::<Tag>(&router, &path).name, "pasen");
+            assert_eq!(status(&router, "DELETE", &path), StatusCode::NO_CONTENT);
+            assert_eq!(status(&router, "GET", &path), StatusCode::NOT_FOUND);
+            assert_eq!(status(&router, "DELETE", &path), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn replace_db() {
+        for router in routers() {
+            let db = db();
+            assert_eq!(
+                send_json(&router, "POST", "/db", &db),
+                StatusCode::NO_CONTENT
+            );
+            let stored = get::<Db>(&router, "/db");
+            let mut titles = stored
+                .lyrics
+                .iter()
+                .map(|lyric| lyric.title.as_str())
+                .collect::<Vec<_>>();
+            titles.sort();
+            assert_eq!(titles, vec!["Dodenrit", "Sofietje"]);
+            assert_eq!(stored.playlists[0].id, db.playlists[0].id);
+            assert_eq!(stored.playlists[0].members, db.playlists[0].members);
+
+            let ndjson = send(
+                &router,
+                request("GET", "/db").header(header::ACCEPT, "application/x-ndjson"),
+                Body::empty(),
+            );
+            assert_eq!(
+                bytes(ndjson)
+                    .split(|b| *b == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .count(),
+                3
+            );
+
+            let archive = send(&router, request("GET", "/db/archive"), Body::empty());
+            assert_eq!(archive.status(), StatusCode::OK);
+            let archive = bytes(archive);
+            assert_eq!(
+                send_json(&router, "POST", "/db", &Db::default()),
+                StatusCode::NO_CONTENT
+            );
+            assert!(get::<Db>(&router, "/db").lyrics.is_empty());
+            assert_eq!(
+                send(&router, request("POST", "/db/archive"), Body::from(archive)).status(),
+                StatusCode::NO_CONTENT
+            );
+            assert_eq!(get::<Db>(&router, "/db").lyrics.len(), 2);
+            assert_eq!(status(&router, "GET", "/db/openlyrics"), StatusCode::OK);
+            let smaller = Db {
+                lyrics: db.lyrics[..1].to_vec(),
+                playlists: vec![],
+            };
+            for replacement in [&db, &db, &smaller] {
+                assert_eq!(
+                    send_json(&router, "POST", "/db", replacement),
+                    StatusCode::NO_CONTENT
+                );
+            }
+            let stored = get::<Db>(&router, "/db");
+            assert_eq!(stored.lyrics.len(), 1);
+            assert_eq!(stored.lyrics[0].id, db.lyrics[0].id);
+            assert!(stored.playlists.is_empty());
+            let uuid = get::<String>(&router, "/uuid/67e55044-10b1-426f-9247-bb680e5fe0c8");
+            assert!(uuid.parse::<Uuid>().is_ok());
+        }
+    }
+
+    /// koor:zingen and orkest:spelen, the credentials of two tenants
+    const KOOR_AUTHORIZATION: &str = "Basic a29vcjp6aW5nZW4=";
+    const ORKEST_AUTHORIZATION: &str = "Basic b3JrZXN0OnNwZWxlbg==";
+
+    #[test]
+    fn tenants() {
+        let router = create(Database::key_value(MemoryStore::default()));
+        let koor = serde_json::json!({
+            "name": "koor",
+            "host": "koor.example.org",
+            "username": "koor",
+            "password": "zingen",
+        });
+        let orkest = serde_json::json!({
+            "name": "orkest",
+            "username": "orkest",
+            "password": "spelen",
+        });
+        let admin = |method: &str, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(format!("{API}{path}"))
+                .header(header::AUTHORIZATION, ADMIN_AUTHORIZATION)
+                .header(header::CONTENT_TYPE, "application/json")
+        };
+        assert_eq!(
+            send_json(&router, "POST", "/tenant", &koor),
+            StatusCode::UNAUTHORIZED
+        );
+        for tenant in [&koor, &orkest] {
+            let created = send(
+                &router,
+                admin("POST", "/tenant"),
+                Body::from(tenant.to_string()),
+            );
+            assert_eq!(created.status(), StatusCode::CREATED);
+        }
+        for invalid in [
+            serde_json::json!({ "name": "kerk" }),
+            serde_json::json!({ "name": "default", "username": "a", "password": "b" }),
+        ] {
+            let refused = send(
+                &router,
+                admin("POST", "/tenant"),
+                Body::from(invalid.to_string()),
+            );
+            assert_eq!(refused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+
+        let in_tenant = |tenant: &str, authorization: &str, method: &str, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(format!("/lipl/t/{tenant}/api/v1{path}"))
+                .header(header::AUTHORIZATION, authorization)
+        };
+        let lyric = lyric("Sofietje");
+        let inserted = send(
+            &router,
+            in_tenant("koor", KOOR_AUTHORIZATION, "POST", "/lyric")
+                .header(header::CONTENT_TYPE, "application/json"),
+            Body::from(serde_json::to_vec(&lyric).unwrap()),
+        );
+        assert_eq!(inserted.status(), StatusCode::CREATED);
+        assert!(get::<Vec<Lyric>>(&router, "/lyric").is_empty());
+        let by_prefix = send(
+            &router,
+            in_tenant("koor", KOOR_AUTHORIZATION, "GET", "/lyric"),
+            Body::empty(),
+        );
+        assert_eq!(
+            serde_json::from_slice::<Vec<Lyric>>(&bytes(by_prefix))
+                .unwrap()
+                .len(),
+            1
+        );
+        let by_host = |authorization: &str| {
+            Request::builder()
+                .uri(format!("{API}/lyric"))
+                .header(header::HOST, "koor.example.org:3000")
+                .header(header::AUTHORIZATION, authorization)
+        };
+        let response = send(&router, by_host(KOOR_AUTHORIZATION), Body::empty());
+        assert_eq!(
+            serde_json::from_slice::<Vec<Lyric>>(&bytes(response)).unwrap()[0].id,
+            lyric.id
+        );
+
+        for (request, case) in [
+            (
+                in_tenant("koor", ORKEST_AUTHORIZATION, "GET", "/lyric"),
+                "other tenant",
+            ),
+            (
+                in_tenant("orkest", KOOR_AUTHORIZATION, "POST", "/db"),
+                "other tenant",
+            ),
+            (
+                in_tenant("koor", AUTHORIZATION, "GET", "/lyric"),
+                "app credentials",
+            ),
+            (by_host(ORKEST_AUTHORIZATION), "other tenant by host"),
+            (by_host(AUTHORIZATION), "app credentials by host"),
+            (
+                Request::builder().uri("/lipl/t/koor/api/v1/lyric"),
+                "no credentials",
+            ),
+            (
+                Request::builder().uri("/lipl/t/kerk/api/v1/lyric"),
+                "no credentials for an unknown tenant",
+            ),
+        ] {
+            assert_eq!(
+                send(&router, request, Body::empty()).status(),
+                StatusCode::UNAUTHORIZED,
+                "{case}"
+            );
+        }
+
+        assert_eq!(
+            send(
+                &router,
+                in_tenant("kerk", KOOR_AUTHORIZATION, "GET", "/lyric"),
+                Body::empty()
+            )
+            .status(),
+            StatusCode::NOT_FOUND
+        );
+        let tenants = send(&router, admin("GET", "/tenant"), Body::empty());
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&bytes(tenants)).unwrap(),
+            serde_json::json!([
+                { "name": "koor", "host": "koor.example.org", "username": "koor" },
+                { "name": "orkest", "username": "orkest" },
+            ])
+        );
+        assert_eq!(
+            send(&router, admin("DELETE", "/tenant/koor"), Body::empty()).status(),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            send(
+                &router,
+                in_tenant("koor", KOOR_AUTHORIZATION, "GET", "/lyric"),
+                Body::empty()
+            )
+            .status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn without_admin() {
+        let router = create_router(
+            Database::sqlite(NativeStorage::open_in_memory().unwrap()),
+            credentials("paul", "password"),
+            None,
+        );
+        assert!(get::<Vec<Lyric>>(&router, "/lyric").is_empty());
+        assert_eq!(status(&router, "GET", "/tenant"), StatusCode::NOT_FOUND);
+        let prefixed = Request::builder()
+            .uri("/lipl/t/koor/api/v1/lyric")
+            .header(header::AUTHORIZATION, AUTHORIZATION);
+        assert_eq!(
+            send(&router, prefixed, Body::empty()).status(),
+            StatusCode::NOT_FOUND
+        );
+    }
 }
@@ -0,0 +1,56 @@
+use model::Uuid;
+use std::time::{Duration, Instant};
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+const MAX_REQUEST_ID_LENGTH: usize = 200;
+
+/// What is known about the request being handled, carried in the request extensions
+#[derive(Clone, Debug)]
+pub struct Context {
+    /// Taken from the `X-Request-Id` header of the request or generated, echoed in the response
+    pub id: String,
+    pub start: Instant,
+    /// Name of the user once the credentials are checked
+    pub user: Option<String>,
+}
+
+impl Context {
+    /// Request ids that are empty, too long or not visible ascii are replaced with a new one,
+    /// so the id can always be logged and sent back as header value
+    pub fn new(request_id: Option<&str>) -> Self {
+        let id = request_id
+            .map(str::trim)
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LENGTH
+                    && id.chars().all(|c| c.is_ascii_graphic())
+            })
+            .map(String::from)
+            .unwrap_or_else(|| Uuid::default().to_string());
+        Self {
+            id,
+            start: Instant::now(),
+            user: None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Context;
+
+    #[test]
+    fn request_id() {
+        assert_eq!(Context::new(Some(" abc-123 ")).id, "abc-123");
+        for id in [None, Some(""), Some("a b"), Some("é")] {
+            let generated = Context::new(id).id;
+            assert!(generated.parse::<model::Uuid>().is_ok(), "{id:?}");
+        }
+        assert_ne!(Context::new(None).id, Context::new(None).id);
+    }
+}
@@ -1,15 +1,48 @@
-use axum::Json;
-use axum::extract::Path;
-use axum::http::{HeaderMap, StatusCode};
-use axum::response::IntoResponse;
-use model::response::if_none_match;
-use model::{Db, Etag, Lyric, LyricPost, Playlist, Uuid};
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, RawQuery, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use futures::{TryStreamExt, stream};
+use model::archive;
+use model::chordpro;
+use model::dump;
+use model::error::Error;
+use model::member::Member;
+use model::openlyrics;
+use model::patch::Patch;
+use model::response::{if_match, if_none_match};
+use model::songbook::{self, Format};
+use model::validation::Violation;
+use model::{
+    Db, Etag, Include, Lyric, LyricPost, LyricQuery, MemberMove, MemberPost, Playlist,
+    PlaylistQuery, Tag, TagPost, Tenant, Transpose, Uuid,
+};
+use std::collections::{BTreeSet, HashMap};
 
-use crate::{Result, persistence::Connection};
+use crate::{
+    Result,
+    context::Context,
+    persistence::{Connection, Database, schema_version},
+};
 
-pub async fn get_lyric_list(headers: HeaderMap) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
-    let lyrics = connection.select_lyric().await?;
+const ACCEPT: &str = "accept";
+const APPLICATION_JSON: &str = "application/json";
+
+pub async fn get_lyric_list(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    headers: HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+    let query = LyricQuery::from_query_string(query.as_deref().unwrap_or_default())?;
+    let connection = database.open(&context).await?;
+    let lyrics = connection
+        .select_lyric_by_query(&query)
+        .await?
+        .into_iter()
+        .map(|lyric| query.apply(lyric))
+        .collect::<Vec<_>>();
     if Some(lyrics.etag()) == if_none_match(&headers) {
         Ok(StatusCode::NOT_MODIFIED.into_response())
     } else {
@@ -17,55 +50,213 @@ pub async fn get_lyric_list(headers: HeaderMap) -> Result<impl IntoResponse> {
     }
 }
 
-pub async fn get_lyric(headers: HeaderMap, Path(id): Path<String>) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+pub async fn get_lyric(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+    let query = LyricQuery::for_lyric(query.as_deref().unwrap_or_default())?;
+    if let Some(id) = id.strip_suffix(chordpro::EXTENSION) {
+        return get_lyric_chordpro(&database, &context, id, query).await;
+    }
+    if let Some(id) = id.strip_suffix(openlyrics::EXTENSION) {
+        return get_lyric_openlyrics(&database, &context, id).await;
+    }
+    let connection = database.open(&context).await?;
     match connection.select_lyric_by_id(&id).await? {
         Some(lyric) => {
-            if Some(lyric.etag()) == if_none_match(&headers) {
-                Ok(StatusCode::NOT_MODIFIED.into_response())
-            } else {
-                Ok(Json(lyric).into_response())
+            // The etag of the stored lyric, so it can be used with If-Match whatever representation is asked for
+            let etag = lyric.etag();
+            let lyric = query.apply(lyric);
+            match Format::from_accept(accept(&headers)) {
+                Format::Json if Some(&etag) == if_none_match(&headers).as_ref() => {
+                    Ok(StatusCode::NOT_MODIFIED.into_response())
+                }
+                Format::Json => Ok((
+                    [
+                        (header::ETAG, format!("\"{etag}\"").as_str()),
+                        (header::VARY, ACCEPT),
+                    ],
+                    Json(lyric),
+                )
+                    .into_response()),
+                format => Ok(printable(format, songbook::lyric(&lyric, format))),
             }
         }
         None => Ok(StatusCode::NOT_FOUND.into_response()),
     }
 }
 
-pub async fn insert_lyric(Json(lyric): Json<Lyric>) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
-    connection
-        .insert_lyric(&lyric)
-        .await
-        .map(|_| StatusCode::CREATED.into_response())
+/// The lyric in ChordPro format, `GET /lyric/{id}.cho`
+async fn get_lyric_chordpro(
+    database: &Database,
+    context: &Context,
+    id: &str,
+    query: LyricQuery,
+) -> Result<Response> {
+    let query = LyricQuery {
+        with: vec![Include::Chords],
+        ..query
+    };
+    let connection = database.open(context).await?;
+    match connection.select_lyric_by_id(id).await? {
+        Some(lyric) => Ok((
+            [(header::CONTENT_TYPE, chordpro::CONTENT_TYPE)],
+            chordpro::to_chordpro(&query.apply(lyric)),
+        )
+            .into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// The lyric in OpenLyrics format, `GET /lyric/{id}.xml`
+async fn get_lyric_openlyrics(
+    database: &Database,
+    context: &Context,
+    id: &str,
+) -> Result<Response> {
+    let connection = database.open(context).await?;
+    match connection.select_lyric_by_id(id).await? {
+        Some(lyric) => Ok((
+            [(header::CONTENT_TYPE, openlyrics::CONTENT_TYPE)],
+            openlyrics::to_openlyrics(&lyric),
+        )
+            .into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
 }
 
+/// Creates a lyric for every song in the ChordPro file and responds with their ids
+pub async fn import_chordpro(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    body: String,
+) -> Result<impl IntoResponse> {
+    insert_imported(&database, &context, chordpro::from_chordpro(&body)).await
+}
+
+/// Creates a lyric for every song in the OpenLyrics document or collection and responds with their ids
+pub async fn import_openlyrics(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    body: String,
+) -> Result<impl IntoResponse> {
+    insert_imported(&database, &context, openlyrics::from_openlyrics(&body)?).await
+}
+
+async fn insert_imported(
+    database: &Database,
+    context: &Context,
+    lyrics: Vec<Lyric>,
+) -> Result<impl IntoResponse + use<>> {
+    if lyrics.is_empty() {
+        return Err(Error::Validation(vec![Violation::new(
+            "body",
+            "no songs found",
+        )]));
+    }
+    let connection = database.open(context).await?;
+    connection.insert_lyrics(&lyrics).await?;
+    let ids = lyrics.into_iter().map(|lyric| lyric.id).collect::<Vec<_>>();
+    Ok((StatusCode::CREATED, Json(ids)))
+}
+
+/// Json, or plain text with the title on the first line. The id of a plain text lyric is in the response.
+pub async fn insert_lyric(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse> {
+    let text = is_text(&headers)?;
+    let lyric = if text {
+        Lyric::from_text(Uuid::default().to_string(), &utf8(&body)?)?
+    } else {
+        serde_json::from_slice::<Lyric>(&body).map_err(Error::from_body)?
+    };
+    let connection = database.open(&context).await?;
+    connection.insert_lyric(&lyric).await?;
+    if text {
+        Ok((StatusCode::CREATED, Json(lyric.id)).into_response())
+    } else {
+        Ok(StatusCode::CREATED.into_response())
+    }
+}
+
+/// Json, or plain text with the title on the first line.
+/// Plain text only has the title and the parts, so the stored meta data, tags and chords are kept.
 pub async fn update_lyric(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
     Path(id): Path<String>,
-    Json(lyric_post): Json<LyricPost>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Result<impl IntoResponse> {
-    let lyric = Lyric::new(
-        id.to_owned(),
-        lyric_post.title.clone(),
-        lyric_post.parts.clone(),
-    );
-    let connection = Connection::try_open_default(None).await?;
+    let text = is_text(&headers)?;
+    let lyric = if text {
+        Lyric::from_text(id, &utf8(&body)?)?
+    } else {
+        serde_json::from_slice::<LyricPost>(&body)
+            .map_err(Error::from_body)?
+            .into_lyric(id)
+    };
+    let connection = database.open(&context).await?;
+    let lyric = if text {
+        match connection.select_lyric_by_id(&lyric.id).await? {
+            Some(stored) => lyric.keep_meta_and_tags(&stored),
+            None => lyric,
+        }
+    } else {
+        lyric
+    };
     connection
         .update_lyric(&lyric)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
 
-pub async fn delete_lyric(Path(id): Path<String>) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+pub async fn patch_lyric(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse> {
+    let patch = Patch::try_new(content_type(&headers), &body)?;
+    let connection = database.open(&context).await?;
+    connection
+        .patch_lyric(&id, if_match(&headers).as_deref(), &patch)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
+
+pub async fn delete_lyric(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
     connection
         .delete_lyric(&id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
 
-pub async fn get_playlist_list(headers: HeaderMap) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
-    let playlists = connection.select_playlist().await?;
+pub async fn get_playlist_list(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    headers: HeaderMap,
+    Query(query): Query<PlaylistQuery>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    let playlists = connection
+        .select_playlist()
+        .await?
+        .into_iter()
+        .map(|playlist| query.apply(playlist))
+        .collect::<Vec<_>>();
     if Some(playlists.etag()) == if_none_match(&headers) {
         Ok(StatusCode::NOT_MODIFIED.into_response())
     } else {
@@ -73,61 +264,389 @@ pub async fn get_playlist_list(headers: HeaderMap) -> Result<impl IntoResponse>
     }
 }
 
-pub async fn get_playlist(Path(id): Path<String>, headers: HeaderMap) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+pub async fn get_playlist(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Query(query): Query<PlaylistQuery>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
     match connection.select_playlist_by_id(&id).await? {
         Some(playlist) => {
-            if Some(playlist.etag()) == if_none_match(&headers) {
-                Ok(StatusCode::NOT_MODIFIED.into_response())
-            } else {
-                Ok(Json(playlist).into_response())
+            // The etag of the stored playlist, like the lyric, whatever representation is asked for
+            let etag = playlist.etag();
+            let playlist = query.apply(playlist);
+            match Format::from_accept(accept(&headers)) {
+                Format::Json if Some(&etag) == if_none_match(&headers).as_ref() => {
+                    Ok(StatusCode::NOT_MODIFIED.into_response())
+                }
+                Format::Json => Ok((
+                    [
+                        (header::ETAG, format!("\"{etag}\"").as_str()),
+                        (header::VARY, ACCEPT),
+                    ],
+                    Json(playlist),
+                )
+                    .into_response()),
+                format => {
+                    let lyrics = member_lyrics(&connection, &playlist)
+                        .await?
+                        .into_iter()
+                        .map(|(_, lyric)| lyric)
+                        .collect::<Vec<_>>();
+                    Ok(printable(
+                        format,
+                        songbook::playlist(&playlist, &lyrics, format),
+                    ))
+                }
             }
         }
         None => Ok(StatusCode::NOT_FOUND.into_response()),
     }
 }
 
-pub async fn insert_playlist(Json(playlist): Json<Playlist>) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+/// The lyrics of a playlist in one ChordPro file, transposed to the key of the member when given
+pub async fn export_playlist_chordpro(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    let Some(playlist) = connection.select_playlist_by_id(&id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let lyrics = member_lyrics(&connection, &playlist)
+        .await?
+        .into_iter()
+        .map(
+            |(member, lyric)| match member.details.key.and_then(|key| key.parse().ok()) {
+                Some(key) => lyric.transposed(Transpose::To(key)),
+                None => lyric,
+            },
+        )
+        .collect::<Vec<_>>();
+    Ok((
+        [(header::CONTENT_TYPE, chordpro::CONTENT_TYPE)],
+        chordpro::to_songbook(&lyrics),
+    )
+        .into_response())
+}
+
+/// The lyrics of the playlist members in playlist order, members without lyric are skipped
+async fn member_lyrics(
+    connection: &Connection,
+    playlist: &Playlist,
+) -> Result<Vec<(Member, Lyric)>> {
+    let ids = playlist
+        .members
+        .iter()
+        .map(|member| member.lyric_id.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let lyrics = connection
+        .select_lyric_by_ids(&ids)
+        .await?
+        .into_iter()
+        .map(|lyric| (lyric.id.clone(), lyric))
+        .collect::<HashMap<_, _>>();
+    Ok(playlist
+        .members
+        .iter()
+        .filter_map(|member| {
+            lyrics
+                .get(&member.lyric_id)
+                .map(|lyric| (member.clone(), lyric.clone()))
+        })
+        .collect())
+}
+
+pub async fn insert_playlist(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Json(playlist): Json<Playlist>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
     connection
-        .insert_playlist(&playlist, true)
+        .insert_playlist(&playlist)
         .await
         .map(|_| StatusCode::CREATED)
 }
 
 pub async fn update_playlist(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
     Path(_): Path<String>,
     Json(playlist): Json<Playlist>,
 ) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+    let connection = database.open(&context).await?;
     connection
         .update_playlist(&playlist)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
 
-pub async fn delete_playlist(Path(id): Path<String>) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+pub async fn patch_playlist(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse> {
+    let patch = Patch::try_new(content_type(&headers), &body)?;
+    let connection = database.open(&context).await?;
+    connection
+        .patch_playlist(&id, if_match(&headers).as_deref(), &patch)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
+
+pub async fn delete_playlist(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
     connection
         .delete_playlist_by_id(&id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
 
-pub async fn replace_db(Json(db): Json<Db>) -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+pub async fn insert_member(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+    Json(member): Json<MemberPost>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .insert_member(&id, &member)
+        .await
+        .map(|found| found_or(found, StatusCode::CREATED))
+}
+
+pub async fn delete_member(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path((id, position)): Path<(String, usize)>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .delete_member(&id, position)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
+
+pub async fn move_members(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+    Json(moves): Json<Vec<MemberMove>>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .move_members(&id, &moves)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
+
+pub async fn get_tag_list(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection.select_tag().await.map(Json)
+}
+
+pub async fn get_tag(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    match connection.select_tag_by_id(&id).await? {
+        Some(tag) => Ok(Json(tag).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+pub async fn insert_tag(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Json(tag): Json<Tag>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .insert_tag(&tag)
+        .await
+        .map(|_| StatusCode::CREATED)
+}
+
+pub async fn update_tag(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+    Json(tag): Json<TagPost>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .update_tag(&id, &tag)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
+
+pub async fn delete_tag(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .delete_tag(&id)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
+
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+}
+
+/// Plain text or json, including json types like `application/vnd.lipl+json`.
+/// Other media types are not supported.
+fn is_text(headers: &HeaderMap) -> Result<bool> {
+    let media_type = content_type(headers)
+        .and_then(|c| c.split(';').next())
+        .map(|m| m.trim().to_ascii_lowercase());
+    match media_type.as_deref() {
+        Some(songbook::TEXT_PLAIN) => Ok(true),
+        Some(APPLICATION_JSON) => Ok(false),
+        Some(media_type)
+            if media_type.starts_with("application/") && media_type.ends_with("+json") =>
+        {
+            Ok(false)
+        }
+        _ => Err(Error::UnsupportedMediaType),
+    }
+}
+
+fn utf8(body: &[u8]) -> Result<String> {
+    String::from_utf8(body.to_vec()).map_err(|_| Error::Body)
+}
+
+fn accept(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::ACCEPT).and_then(|h| h.to_str().ok())
+}
+
+fn printable(format: Format, body: String) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::VARY, ACCEPT),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn found_or(found: bool, status: StatusCode) -> StatusCode {
+    if found { status } else { StatusCode::NOT_FOUND }
+}
+
+pub async fn replace_db(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    Json(db): Json<Db>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .replace_db(&db)
+        .await
+        .map(|_| StatusCode::NO_CONTENT)
+}
+
+/// Streams the lyrics and then the playlists while they are read from the database
+pub async fn get_db(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    headers: HeaderMap,
+    Query(query): Query<PlaylistQuery>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    let lyrics = connection.stream_lyric().await?;
+    // The playlists are only queried after the last lyric is written
+    let playlists = stream::once(connection.stream_playlist())
+        .try_flatten()
+        .map_ok(move |playlist| query.apply(playlist));
+    if dump::wants_ndjson(accept(&headers)) {
+        Ok((
+            [
+                (header::CONTENT_TYPE, dump::APPLICATION_NDJSON),
+                (header::VARY, ACCEPT),
+            ],
+            Body::from_stream(dump::ndjson(lyrics, playlists)),
+        ))
+    } else {
+        Ok((
+            [
+                (header::CONTENT_TYPE, APPLICATION_JSON),
+                (header::VARY, ACCEPT),
+            ],
+            Body::from_stream(dump::json(lyrics, playlists)),
+        ))
+    }
+}
+
+pub async fn get_db_archive(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    let db = Db {
+        lyrics: connection.select_lyric().await?,
+        playlists: connection.select_playlist().await?,
+    };
+    let archive = archive::to_archive(&db, schema_version())?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, archive::CONTENT_TYPE),
+            (
+                header::CONTENT_DISPOSITION,
+                r#"attachment; filename="lipl.zip""#,
+            ),
+        ],
+        archive,
+    ))
+}
+
+/// Replaces the database with the one in the archive, after checking the manifest
+pub async fn restore_db_archive(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+    body: Bytes,
+) -> Result<impl IntoResponse> {
+    let db = archive::from_archive(&body, schema_version())?;
+    let connection = database.open(&context).await?;
     connection
         .replace_db(&db)
         .await
         .map(|_| StatusCode::NO_CONTENT)
 }
 
-pub async fn get_db() -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(None).await?;
+/// All lyrics as OpenLyrics collection, playlists have no place in OpenLyrics
+pub async fn get_db_openlyrics(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
     let lyrics = connection.select_lyric().await?;
-    let playlists = connection.select_playlist().await?;
-    let db = Db { lyrics, playlists };
-    Ok(Json(db))
+    Ok((
+        [(header::CONTENT_TYPE, openlyrics::CONTENT_TYPE)],
+        openlyrics::to_collection(&lyrics),
+    ))
 }
 
 pub async fn get_uuid(Path(id): Path<String>) -> Result<impl IntoResponse> {
@@ -135,7 +654,48 @@ pub async fn get_uuid(Path(id): Path<String>) -> Result<impl IntoResponse> {
     Ok(Json(uuid.to_string()))
 }
 
-pub async fn get_user_list() -> Result<impl IntoResponse> {
-    let connection = Connection::try_open_default(Some(include_str!("../migrations.sql"))).await?;
+pub async fn get_user_list(
+    Extension(database): Extension<Database>,
+    Extension(context): Extension<Context>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
     connection.select_user().await.map(Json)
 }
+
+/// The tenants are kept in the database of the Spin app itself, whatever tenant the request is for
+pub async fn get_tenant_list(
+    State(database): State<Database>,
+    Extension(context): Extension<Context>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection.select_tenant().await.map(Json)
+}
+
+pub async fn insert_tenant(
+    State(database): State<Database>,
+    Extension(context): Extension<Context>,
+    Json(tenant): Json<Tenant>,
+) -> Result<impl IntoResponse> {
+    if database.label() == Some(tenant.name.as_str()) {
+        return Err(Error::Validation(vec![Violation::new(
+            "name",
+            "is reserved",
+        )]));
+    }
+    let connection = database.open(&context).await?;
+    connection.insert_tenant(&tenant).await?;
+    database.tenant_added();
+    Ok(StatusCode::CREATED)
+}
+
+pub async fn delete_tenant(
+    State(database): State<Database>,
+    Extension(context): Extension<Context>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse> {
+    let connection = database.open(&context).await?;
+    connection
+        .delete_tenant(&name)
+        .await
+        .map(|found| found_or(found, StatusCode::NO_CONTENT))
+}
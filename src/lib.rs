@@ -1,74 +1,96 @@
 use axum::http::StatusCode;
-use model::{Uuid, error::Error};
+use model::{error::Error, validation::DEFAULT_LABEL};
 use spin_sdk::{
     http::{IntoResponse, Request},
     http_service, variables,
 };
-use std::{sync::OnceLock, time::Instant};
 use tower_service::Service;
 
-use crate::api::create_router;
+use crate::{
+    api::{Credentials, create_router},
+    context::{Context, X_REQUEST_ID},
+    persistence::Database,
+};
 
 mod api;
+mod context;
 pub mod handler;
 mod message;
 pub mod persistence;
 
 type Result<T> = std::result::Result<T, Error>;
 
-fn request_id() -> &'static Uuid {
-    static REQUEST_ID: OnceLock<Uuid> = OnceLock::new();
-    REQUEST_ID.get_or_init(Uuid::default)
-}
-
-fn now() -> &'static Instant {
-    static NOW: OnceLock<Instant> = OnceLock::new();
-    NOW.get_or_init(Instant::now)
-}
-
 fn header_value<'a>(req: &'a Request, name: &'a str) -> Option<&'a str> {
     req.headers().get(name).and_then(|h| h.to_str().ok())
 }
 
 /// A simple Spin HTTP component.
 #[http_service]
-async fn handle_lipl_storage_spin(req: Request) -> impl IntoResponse {
-    let username = variables::get("lipl_username").await.unwrap();
-    let password = variables::get("lipl_password").await.unwrap();
+async fn handle_lipl_storage_spin(mut req: Request) -> impl IntoResponse {
+    let context = Context::new(header_value(&req, X_REQUEST_ID));
+    let credentials = Credentials {
+        username: variables::get("lipl_username").await.unwrap(),
+        password: variables::get("lipl_password").await.unwrap(),
+    };
+    let admin = match (
+        variables::get("lipl_admin_username").await,
+        variables::get("lipl_admin_password").await,
+    ) {
+        (Ok(username), Ok(password)) if !username.is_empty() && !password.is_empty() => {
+            Some(Credentials { username, password })
+        }
+        _ => None,
+    };
+    let storage = variables::get("lipl_storage")
+        .await
+        .unwrap_or_else(|_| "sqlite".to_owned());
+    let label = variables::get("lipl_database")
+        .await
+        .unwrap_or_else(|_| DEFAULT_LABEL.to_owned());
 
-    message::request_received(req.uri().path(), req.method());
+    message::request_received(&context, req.uri().path(), req.method());
 
     if let Some(referer) = header_value(&req, "referer") {
-        message::dump_header("referer", referer);
+        message::dump_header(&context, "referer", referer);
     }
 
     if let Some(referer) = header_value(&req, "host") {
-        message::dump_header("host", referer);
+        message::dump_header(&context, "host", referer);
     }
 
     if req.uri().path() == "/lipl/api/v1/health" {
-        return StatusCode::NO_CONTENT.into_response();
+        return axum::http::Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(X_REQUEST_ID, context.id.as_str())
+            .body(axum::body::Body::empty())
+            .map_err(|e| {
+                spin_sdk::wasip3::http::types::ErrorCode::InternalError(Some(e.to_string()))
+            })
+            .and_then(|r| r.into_response());
+    }
+
+    let database = Database::from_name(&storage, &label);
+    if let Err(error) = database
+        .seed_user(&context, &credentials.username, &credentials.password)
+        .await
+    {
+        message::seed_failed(&context, error);
     }
 
-    create_router(&username, &password)
+    req.extensions_mut().insert(context.clone());
+    let response = create_router(database, credentials, admin)
         .call(req)
         .await
-        .map_err(|e| spin_sdk::wasip3::http::types::ErrorCode::InternalError(Some(e.to_string())))
+        .map_err(|e| spin_sdk::wasip3::http::types::ErrorCode::InternalError(Some(e.to_string())));
+    // Only the context that went along with the request knows the authenticated user
+    let context = response
+        .as_ref()
+        .ok()
+        .and_then(|r| r.extensions().get::<Context>())
+        .cloned()
+        .unwrap_or(context);
+    response
         .and_then(|r| r.into_response())
-        .inspect_err(|error| {
-            eprintln!(
-                "{}: Error {} after {} milliseconds",
-                request_id(),
-                error,
-                now().elapsed().as_millis()
-            );
-        })
-        .inspect(|x| {
-            println!(
-                "{}: Success {} after {} milliseconds",
-                request_id(),
-                x.get_status_code(),
-                now().elapsed().as_millis()
-            );
-        })
+        .inspect_err(|error| message::response_failed(&context, error))
+        .inspect(|x| message::response_sent(&context, x.get_status_code()))
 }
@@ -1,48 +1,74 @@
-use std::{fmt::Display, sync::OnceLock, time::Instant};
+use std::fmt::Display;
 
-use model::Uuid;
+use crate::context::Context;
 
-pub fn request_id() -> &'static Uuid {
-    static REQUEST_ID: OnceLock<Uuid> = OnceLock::new();
-    REQUEST_ID.get_or_init(Uuid::default)
-}
-
-pub fn now() -> &'static Instant {
-    static NOW: OnceLock<Instant> = OnceLock::new();
-    NOW.get_or_init(Instant::now)
-}
-
-pub fn db_connection_established() {
+pub fn db_connection_established(context: &Context, kind: &str, label: &str) {
     println!(
-        "{}: connection with default sqlite db established after {} microseconds",
-        request_id(),
-        now().elapsed().as_micros(),
+        "{}: connection with {} {} established after {} microseconds",
+        context.id,
+        kind,
+        label,
+        context.elapsed().as_micros(),
     );
 }
 
-pub fn user_authenticated(user: impl Display) {
+pub fn user_authenticated(context: &Context, user: impl Display) {
     println!(
         "{}: User {} after {} milliseconds",
-        request_id(),
+        context.id,
         user,
-        now().elapsed().as_millis(),
+        context.elapsed().as_millis(),
     );
 }
 
-pub fn request_received(path: impl Display, method: impl Display) {
+pub fn request_received(context: &Context, path: impl Display, method: impl Display) {
     println!(
         "{}: received {} {} request after {} milliseconds",
-        request_id(),
+        context.id,
         method.to_string().to_lowercase(),
         path,
-        now().elapsed().as_millis()
+        context.elapsed().as_millis()
     );
 }
 
-pub fn rollback_failure(error: impl Display) {
-    eprintln!("{}: Cannot rollback {}", request_id(), error);
+pub fn rollback_failure(context: &Context, error: impl Display, rollback: impl Display) {
+    eprintln!(
+        "{}: Cannot rollback after {}: {}",
+        context.id, error, rollback
+    );
 }
 
-pub fn dump_header(name: &str, value: &str) {
-    println!("{}: {} = {}", request_id(), name, value);
+pub fn seed_failed(context: &Context, error: impl Display) {
+    eprintln!(
+        "{}: Cannot add the user to the store: {}",
+        context.id, error
+    );
+}
+
+pub fn dump_header(context: &Context, name: &str, value: &str) {
+    println!("{}: {} = {}", context.id, name, value);
+}
+
+fn user(context: &Context) -> &str {
+    context.user.as_deref().unwrap_or("anonymous")
+}
+
+pub fn response_sent(context: &Context, status: impl Display) {
+    println!(
+        "{}: Success {} for {} after {} milliseconds",
+        context.id,
+        status,
+        user(context),
+        context.elapsed().as_millis()
+    );
+}
+
+pub fn response_failed(context: &Context, error: impl Display) {
+    eprintln!(
+        "{}: Error {} for {} after {} milliseconds",
+        context.id,
+        error,
+        user(context),
+        context.elapsed().as_millis()
+    );
 }
@@ -1,512 +1,243 @@
-use spin_sdk::{sqlite::Value, wit_bindgen::block_on};
-use spin_sqlite_connection::SqliteConnection;
-
-use super::message;
-use model::{error::Error, parts::Parts, Db, Lyric, LyricId, Playlist, User, Uuid};
+use futures::{StreamExt, stream::BoxStream};
+use spin_sqlite_connection::Storage;
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use crate::context::Context;
+use model::{
+    Db, Lyric, LyricQuery, MemberMove, MemberPost, Playlist, Tag, TagPost, Tenant, User,
+    error::Error,
+    member::Member,
+    patch::Patch,
+    validation::{DEFAULT_LABEL, Violation},
+};
+
+mod key_value;
+mod sqlite;
+
+#[cfg(test)]
+pub use key_value::MemoryStore;
+pub use key_value::{KeyValueStore, Prefixed};
+pub use sqlite::schema_version;
 
 type Result<T> = std::result::Result<T, Error>;
 
-trait RollBackOnError<T> {
-    fn rollback_on_error(self, connection: &Connection) -> Result<T>;
-}
-
-impl<T> RollBackOnError<T> for Result<T> {
-    fn rollback_on_error(self, connection: &Connection) -> Result<T> {
-        self.inspect_err(|err| {
-            if block_on(connection.roll_back()).is_err() {
-                message::rollback_failure(err);
-            }
-        })
+fn valid(violations: Vec<Violation>) -> Result<()> {
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(violations))
     }
 }
 
-fn first<T: Clone>(list: Vec<T>) -> Option<T> {
-    list.first().cloned()
-}
-
-fn unit<T>(_: T) {}
+/// Labels of the databases this instance found without tenants
+static WITHOUT_TENANTS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
 
-fn map<T, U, F>(f: F) -> impl Fn(Vec<T>) -> Vec<U>
-where
-    F: Fn(T) -> U + Copy,
-{
-    move |list| list.into_iter().map(f).collect()
+fn without_tenants(label: &str) -> bool {
+    WITHOUT_TENANTS
+        .lock()
+        .map(|labels| labels.contains(label))
+        .unwrap_or_default()
 }
-pub struct Connection(SqliteConnection<Error>);
 
-impl Connection {
-    pub async fn try_open_default(migrations: Option<&'static str>) -> Result<Self> {
-        let connection = SqliteConnection::try_open_default(migrations)
-            .await
-            .map(Self)?;
-        message::db_connection_established();
-        connection
-            .0
-            .execute(sql::SQL_FOREIGN_KEYS_ON, vec![])
-            .await?;
-        Ok(connection)
-    }
-
-    pub async fn begin_transaction(&self) -> Result<()> {
-        self.0
-            .execute(sql::SQL_BEGIN_TRANSACTION, vec![])
-            .await
-            .map(unit)
-    }
-
-    pub async fn roll_back(&self) -> Result<()> {
-        self.0.execute(sql::SQL_ROLLBACK, vec![]).await.map(unit)
-    }
-
-    pub async fn commit(&self) -> Result<()> {
-        self.0.execute(sql::SQL_COMMIT, vec![]).await.map(unit)
-    }
-
-    pub async fn is_valid_user(&self, name: &str, password: &str) -> Result<bool> {
-        self.0
-            .query::<User>(
-                sql::SQL_SELECT_USER_BY_NAME_AND_PASSWORD,
-                vec![
-                    Value::Text(name.to_owned()),
-                    Value::Text(password.to_owned()),
-                ],
-            )
-            .await
-            .map(first)
-            .inspect(|user| {
-                if let Some(u) = user {
-                    message::user_authenticated(u);
-                };
-            })
-            .map(|user| user.is_some())
-    }
-
-    pub async fn select_user(&self) -> Result<Vec<User>> {
-        self.0.query::<User>(sql::SQL_SELECT_USER, vec![]).await
-    }
+/// Where the connections of the requests are opened, a sqlite database or key-value store
+/// of the Spin host by label unless other storage is given
+#[derive(Clone)]
+pub enum Database {
+    Sqlite(String),
+    KeyValue(String),
+    SqliteStorage(Arc<dyn Storage>),
+    KeyValueStorage(Arc<dyn KeyValueStore>),
+}
 
-    pub async fn select_lyric(&self) -> Result<Vec<Lyric>> {
-        self.0
-            .query::<Lyric>(sql::SQL_SELECT_LYRIC_LIST, vec![])
-            .await
+impl Default for Database {
+    fn default() -> Self {
+        Self::Sqlite(DEFAULT_LABEL.to_owned())
     }
+}
 
-    pub async fn select_lyric_by_id(&self, id: &str) -> Result<Option<Lyric>> {
-        self.0
-            .query::<Lyric>(sql::SQL_SELECT_LYRIC, vec![Value::Text(id.to_string())])
-            .await
-            .map(first)
+impl Database {
+    pub fn sqlite(storage: impl Storage + 'static) -> Self {
+        Self::SqliteStorage(Arc::new(storage))
     }
 
-    pub async fn delete_lyric(&self, id: &str) -> Result<bool> {
-        self.0
-            .execute(sql::SQL_DELETE_LYRIC, vec![Value::Text(id.to_string())])
-            .await
-            .map(|c| c > 0)
+    pub fn key_value(store: impl KeyValueStore + 'static) -> Self {
+        Self::KeyValueStorage(Arc::new(store))
     }
 
-    pub async fn update_lyric(&self, lyric: &Lyric) -> Result<bool> {
-        let params = vec![
-            Value::Text(lyric.title.clone()),
-            Value::Text(Parts::from(lyric.parts.clone()).to_text()),
-            Value::Text(lyric.id.clone()),
-        ];
-        self.0
-            .execute(sql::SQL_UPDATE_LYRIC, params)
-            .await
-            .map(|c| c > 0)
+    /// The Spin host storage named by the `lipl_storage` variable, `key_value` or `sqlite`,
+    /// with the label from the `lipl_database` variable
+    pub fn from_name(name: &str, label: &str) -> Self {
+        match name {
+            "key_value" => Self::KeyValue(label.to_owned()),
+            _ => Self::Sqlite(label.to_owned()),
+        }
     }
 
-    pub async fn insert_lyric(&self, lyric: &Lyric) -> Result<()> {
-        let params = vec![
-            Value::Text(lyric.id.clone()),
-            Value::Text(lyric.title.clone()),
-            Value::Text(Parts::from(lyric.parts.clone()).to_text()),
-            Value::Text(Uuid::default().to_string()),
-        ];
-        self.0
-            .execute(sql::SQL_INSERT_LYRIC, params)
-            .await
-            .map(unit)
+    /// The label of the database or store of the Spin host, a tenant by that name would share it
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Self::Sqlite(label) | Self::KeyValue(label) => Some(label),
+            Self::SqliteStorage(_) | Self::KeyValueStorage(_) => None,
+        }
     }
 
-    async fn select_members_by_playlist_id(&self, playlist_id: &str) -> Result<Vec<String>> {
-        self.0
-            .query::<LyricId>(
-                sql::SQL_SELECT_MEMBER_LYRICS,
-                vec![Value::Text(playlist_id.to_string())],
-            )
-            .await
-            .map(map::<LyricId, _, _>(|lid| lid.0))
+    /// The tenant with `host` as host name. A database of the Spin host that had no tenants
+    /// is not asked again by this instance, until a tenant is added.
+    pub async fn select_tenant_by_host(
+        &self,
+        context: &Context,
+        host: &str,
+    ) -> Result<Option<Tenant>> {
+        let label = self.label();
+        if label.is_some_and(without_tenants) {
+            return Ok(None);
+        }
+        let tenants = self.open(context).await?.select_tenant().await?;
+        if let (true, Some(label), Ok(mut labels)) =
+            (tenants.is_empty(), label, WITHOUT_TENANTS.lock())
+        {
+            labels.insert(label.to_owned());
+        }
+        Ok(tenants.into_iter().find(|tenant| {
+            tenant
+                .host
+                .as_ref()
+                .is_some_and(|h| h.eq_ignore_ascii_case(host))
+        }))
+    }
+
+    /// To look up tenants by host name again after a tenant is added
+    pub fn tenant_added(&self) {
+        if let (Some(label), Ok(mut labels)) = (self.label(), WITHOUT_TENANTS.lock()) {
+            labels.remove(label);
+        }
     }
 
-    pub async fn select_playlist(&self) -> Result<Vec<Playlist>> {
-        let mut playlists = self
-            .0
-            .query::<Playlist>(sql::SQL_SELECT_PLAYLIST_LIST, vec![])
-            .await?;
-
-        for playlist in playlists.iter_mut() {
-            let members = self.select_members_by_playlist_id(&playlist.id).await?;
-            for member in members {
-                playlist.members.push(member);
-            }
+    /// Where the data of `tenant` is kept, the database or store of the Spin host labeled with its name.
+    /// Other key-value storage is shared, with the keys of the tenant prefixed by its name.
+    /// Other sqlite storage has no database per tenant, so it fails with not found.
+    pub fn for_tenant(&self, tenant: &str) -> Result<Self> {
+        match self {
+            Self::Sqlite(_) => Ok(Self::Sqlite(tenant.to_owned())),
+            Self::KeyValue(_) => Ok(Self::KeyValue(tenant.to_owned())),
+            Self::SqliteStorage(_) => Err(Error::NotFound),
+            Self::KeyValueStorage(store) => Ok(Self::key_value(Prefixed {
+                store: store.clone(),
+                prefix: format!("tenants/{tenant}/"),
+            })),
         }
-        Ok(playlists)
     }
 
-    pub async fn select_playlist_by_id(&self, id: &str) -> Result<Option<Playlist>> {
-        let result = self
-            .0
-            .query::<Playlist>(sql::SQL_GET_PLAYLIST, vec![Value::Text(id.to_string())])
-            .await
-            .map(first)?;
-        match result {
-            Some(playlist) => {
-                self.select_members_by_playlist_id(&playlist.id)
+    /// Gives a key-value store without users the user with `name` and `password`.
+    /// A sqlite database gets its user from the migrations.
+    pub async fn seed_user(&self, context: &Context, name: &str, password: &str) -> Result<()> {
+        match self {
+            Self::KeyValue(label) => {
+                key_value::Connection::seed(label, context, name, password).await
+            }
+            Self::KeyValueStorage(store) => {
+                key_value::Connection::new(store.clone())
+                    .seed_user(name, password)
                     .await
-                    .map(|members| {
-                        Some(Playlist {
-                            members,
-                            ..playlist
-                        })
-                    })
             }
-            None => Ok(None),
+            Self::Sqlite(_) | Self::SqliteStorage(_) => Ok(()),
         }
     }
 
-    pub async fn delete_playlist_by_id(&self, id: &str) -> Result<()> {
-        self.0
-            .execute(sql::SQL_DELETE_PLAYLIST, vec![Value::Text(id.to_string())])
-            .await
-            .map(unit)
-    }
-
-    pub async fn delete_members_by_playlist_id(&self, playlist_id: &str) -> Result<i64> {
-        self.0
-            .execute(
-                sql::SQL_DELETE_MEMBER,
-                vec![Value::Text(playlist_id.into())],
-            )
-            .await
-            .map(|_| 0)
-    }
-
-    pub async fn insert_members(&self, playlist_id: &str, lyric_ids: &[String]) -> Result<()> {
-        for (i, lyric_id) in lyric_ids.iter().enumerate() {
-            self.0
-                .execute(
-                    sql::SQL_INSERT_MEMBER,
-                    vec![
-                        Value::Text(playlist_id.into()),
-                        Value::Text(lyric_id.clone()),
-                        Value::Integer((i + 1).try_into().unwrap()),
-                    ],
-                )
-                .await?;
+    /// Connection and rollback messages are logged with the id of the request in `context`
+    pub async fn open(&self, context: &Context) -> Result<Connection> {
+        match self {
+            Self::Sqlite(label) => sqlite::Connection::open(label, context)
+                .await
+                .map(Connection::Sqlite),
+            Self::KeyValue(label) => key_value::Connection::open(label, context)
+                .await
+                .map(Connection::KeyValue),
+            Self::SqliteStorage(storage) => sqlite::Connection::try_open(storage.clone(), context)
+                .await
+                .map(Connection::Sqlite),
+            Self::KeyValueStorage(store) => Ok(Connection::KeyValue(key_value::Connection::new(
+                store.clone(),
+            ))),
         }
-        Ok(())
     }
+}
 
-    pub async fn update_playlist(&self, playlist: &Playlist) -> Result<()> {
-        self.begin_transaction().await?;
-        self.delete_members_by_playlist_id(&playlist.id)
-            .await
-            .rollback_on_error(self)?;
-        self.0
-            .execute(
-                sql::SQL_UPDATE_PLAYLIST,
-                vec![
-                    Value::Text(playlist.title.clone()),
-                    Value::Text(Uuid::default().to_string()),
-                    Value::Text(playlist.id.clone()),
-                ],
-            )
-            .await
-            .rollback_on_error(self)?;
-        self.insert_members(&playlist.id, &playlist.members)
-            .await
-            .rollback_on_error(self)?;
-        self.commit().await?;
-        Ok(())
-    }
+pub enum Connection {
+    Sqlite(sqlite::Connection),
+    KeyValue(key_value::Connection),
+}
 
-    pub async fn insert_playlist(&self, playlist: &Playlist, transact: bool) -> Result<()> {
-        if transact {
-            self.begin_transaction().await?;
-        }
+/// Methods with the same signature on both connections
+macro_rules! dispatch {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $output:ty;)*) => {
+        $(
+            pub async fn $name(&self $(, $arg: $ty)*) -> Result<$output> {
+                match self {
+                    Self::Sqlite(connection) => connection.$name($($arg),*).await,
+                    Self::KeyValue(connection) => connection.$name($($arg),*).await,
+                }
+            }
+        )*
+    };
+}
 
-        self.0
-            .execute(
-                sql::SQL_INSERT_PLAYLIST,
-                vec![
-                    Value::Text(playlist.id.clone()),
-                    Value::Text(playlist.title.clone()),
-                    Value::Text(Uuid::default().to_string()),
-                ],
-            )
-            .await
-            .rollback_on_error(self)?;
-        self.insert_members(&playlist.id, &playlist.members)
-            .await
-            .rollback_on_error(self)?;
-        if transact {
-            self.commit().await
-        } else {
-            Ok(())
+impl Connection {
+    dispatch! {
+        fn select_user(&self) -> Vec<User>;
+        fn select_tenant(&self) -> Vec<Tenant>;
+        fn select_tenant_by_name(&self, name: &str) -> Option<Tenant>;
+        fn insert_tenant(&self, tenant: &Tenant) -> ();
+        fn delete_tenant(&self, name: &str) -> bool;
+        fn select_lyric(&self) -> Vec<Lyric>;
+        fn select_lyric_by_query(&self, query: &LyricQuery) -> Vec<Lyric>;
+        fn select_lyric_by_id(&self, id: &str) -> Option<Lyric>;
+        fn select_lyric_by_ids(&self, ids: &[&str]) -> Vec<Lyric>;
+        fn delete_lyric(&self, id: &str) -> bool;
+        fn update_lyric(&self, lyric: &Lyric) -> bool;
+        fn insert_lyric(&self, lyric: &Lyric) -> ();
+        fn insert_lyrics(&self, lyrics: &[Lyric]) -> ();
+        fn select_tag(&self) -> Vec<Tag>;
+        fn select_tag_by_id(&self, id: &str) -> Option<Tag>;
+        fn insert_tag(&self, tag: &Tag) -> ();
+        fn update_tag(&self, id: &str, tag: &TagPost) -> bool;
+        fn delete_tag(&self, id: &str) -> bool;
+        fn select_playlist(&self) -> Vec<Playlist>;
+        fn select_playlist_by_id(&self, id: &str) -> Option<Playlist>;
+        fn delete_playlist_by_id(&self, id: &str) -> ();
+        fn delete_members_by_playlist_id(&self, playlist_id: &str) -> i64;
+        fn insert_members(&self, playlist_id: &str, members: &[Member]) -> ();
+        fn update_playlist(&self, playlist: &Playlist) -> ();
+        fn patch_lyric(&self, id: &str, if_match: Option<&str>, patch: &Patch) -> bool;
+        fn patch_playlist(&self, id: &str, if_match: Option<&str>, patch: &Patch) -> bool;
+        fn insert_playlist(&self, playlist: &Playlist) -> ();
+        fn insert_member(&self, playlist_id: &str, member: &MemberPost) -> bool;
+        fn delete_member(&self, playlist_id: &str, position: usize) -> bool;
+        fn move_members(&self, playlist_id: &str, moves: &[MemberMove]) -> bool;
+        fn update_lyric_list_etag(&self) -> ();
+        fn update_playlist_list_etag(&self) -> ();
+        fn delete_all_lyrics(&self) -> ();
+        fn delete_all_playlists(&self) -> ();
+        fn delete_all_members(&self) -> ();
+        fn replace_db(&self, db: &Db) -> ();
+    }
+
+    /// All lyrics, converted one at a time as they are read
+    pub async fn stream_lyric(&self) -> Result<BoxStream<'static, Result<Lyric>>> {
+        match self {
+            Self::Sqlite(connection) => connection.stream_lyric().await.map(StreamExt::boxed),
+            Self::KeyValue(connection) => connection.stream_lyric().await.map(StreamExt::boxed),
         }
     }
 
-    pub async fn update_lyric_list_etag(&self) -> Result<()> {
-        self.0
-            .execute(
-                sql::SQL_UPDATE_LYRIC_LIST_ETAG,
-                vec![Value::Text(Uuid::default().to_string())],
-            )
-            .await
-            .map(unit)
-    }
-
-    pub async fn update_playlist_list_etag(&self) -> Result<()> {
-        self.0
-            .execute(
-                sql::SQL_UPDATE_PLAYLIST_LIST_ETAG,
-                vec![Value::Text(Uuid::default().to_string())],
-            )
-            .await
-            .map(unit)
-    }
-
-    async fn delete_all(&self, sql: &str) -> Result<()> {
-        self.0.execute(sql, vec![]).await.map(unit)
-    }
-
-    pub async fn delete_all_lyrics(&self) -> Result<()> {
-        self.delete_all(sql::SQL_DELETE_ALL_LYRICS).await
-    }
-
-    pub async fn delete_all_playlists(&self) -> Result<()> {
-        self.delete_all(sql::SQL_DELETE_ALL_PLAYLISTS).await
-    }
-
-    pub async fn delete_all_members(&self) -> Result<()> {
-        self.delete_all(sql::SQL_DELETE_ALL_MEMBERS).await
-    }
-
-    pub async fn replace_db(&self, db: &Db) -> Result<()> {
-        self.begin_transaction().await?;
-
-        self.delete_all_playlists().await.rollback_on_error(self)?;
-        self.delete_all_lyrics().await.rollback_on_error(self)?;
-        self.delete_all_members().await.rollback_on_error(self)?;
-        for lyric in db.lyrics.iter() {
-            self.insert_lyric(lyric).await.rollback_on_error(self)?;
-        }
-        for playlist in db.playlists.iter() {
-            self.insert_playlist(playlist, false)
-                .await
-                .rollback_on_error(self)?;
+    /// All playlists, the stream owns the connection until the last playlist is read
+    pub async fn stream_playlist(self) -> Result<BoxStream<'static, Result<Playlist>>> {
+        match self {
+            Self::Sqlite(connection) => connection.stream_playlist().await.map(StreamExt::boxed),
+            Self::KeyValue(connection) => connection.stream_playlist().await.map(StreamExt::boxed),
         }
-        self.update_lyric_list_etag()
-            .await
-            .rollback_on_error(self)?;
-        self.update_playlist_list_etag()
-            .await
-            .rollback_on_error(self)?;
-        self.commit().await
     }
 }
-
-mod sql {
-    pub const SQL_FOREIGN_KEYS_ON: &str = "PRAGMA foreign_keys = ON";
-    pub const SQL_BEGIN_TRANSACTION: &str = "BEGIN TRANSACTION";
-    pub const SQL_ROLLBACK: &str = "ROLLBACK";
-    pub const SQL_COMMIT: &str = "COMMIT";
-
-    pub const SQL_SELECT_LYRIC_LIST: &str =
-        "SELECT id, title, parts, created, modified, etag FROM lyric ORDER BY title";
-    pub const SQL_SELECT_LYRIC: &str =
-        "SELECT id, title, parts, created, modified, etag FROM lyric WHERE Id=?";
-    pub const SQL_INSERT_LYRIC: &str = "INSERT INTO lyric (id, title, parts, created, modified, etag) VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), ?)";
-    pub const SQL_UPDATE_LYRIC: &str = "UPDATE lyric SET title=?, parts=?, modified=strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE Id=?";
-    pub const SQL_DELETE_LYRIC: &str = "DELETE FROM lyric WHERE Id=?";
-
-    pub const SQL_SELECT_PLAYLIST_LIST: &str =
-        "SELECT id, title, created, modified, etag FROM playlist ORDER BY title";
-    pub const SQL_GET_PLAYLIST: &str =
-        "SELECT id, title, created, modified, etag FROM playlist WHERE Id=?";
-
-    pub const SQL_INSERT_PLAYLIST: &str = "INSERT INTO playlist (id, title, created, modified, etag) VALUES (?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), ?)";
-    pub const SQL_UPDATE_PLAYLIST: &str = "UPDATE playlist SET title = ?, modified = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), etag = ? WHERE id = ?";
-    pub const SQL_DELETE_PLAYLIST: &str = "DELETE FROM playlist WHERE Id=?";
-
-    pub const SQL_SELECT_MEMBER_LYRICS: &str =
-        "SELECT lyric_id FROM member WHERE playlist_id = ? ORDER BY ordering";
-    pub const SQL_INSERT_MEMBER: &str =
-        "INSERT INTO member (playlist_id, lyric_id, ordering) VALUES (?, ?, ?)";
-    pub const SQL_DELETE_MEMBER: &str = "DELETE FROM member WHERE playlist_id = ?";
-
-    pub const SQL_UPDATE_LYRIC_LIST_ETAG: &str =
-        "UPDATE list_etag SET etag = ? WHERE id = 'lyrics'";
-    pub const SQL_UPDATE_PLAYLIST_LIST_ETAG: &str =
-        "UPDATE list_etag SET etag = ? WHERE id = 'playlists'";
-
-    pub const SQL_DELETE_ALL_PLAYLISTS: &str = "DELETE FROM playlist";
-    pub const SQL_DELETE_ALL_LYRICS: &str = "DELETE FROM lyric";
-    pub const SQL_DELETE_ALL_MEMBERS: &str = "DELETE FROM member";
-
-    pub const SQL_SELECT_USER: &str = "SELECT id, name, password FROM user";
-    pub const SQL_SELECT_USER_BY_NAME_AND_PASSWORD: &str =
-        "SELECT id, name, password FROM user WHERE name = ? AND password = ?";
-}
-
-// #[cfg(test)]
-// mod test {
-//     use std::{thread, time::Duration};
-
-//     use model::{Db, Lyric, Playlist, Uuid, convert::TryFromJson, error::Error};
-//     use spin_sdk::sqlite::RowResult;
-
-//     use super::Connection;
-
-//     const MIGRATIONS: &str = include_str!("../migrations.sql");
-
-//     fn open() -> super::Connection {
-//         Connection::try_open_default(Some(MIGRATIONS)).unwrap()
-//     }
-
-//     #[test]
-//     fn test_open_database() {
-//         open();
-//     }
-
-//     #[test]
-//     fn insert_lyric() {
-//         let connection = open();
-
-//         let id = Uuid::default().to_string();
-//         let mut lyric = Lyric::new(id.clone(), "Zie maar hoe je het doet".to_owned(), vec![]);
-//         connection.insert_lyric(&lyric).unwrap();
-
-//         let stored_lyric = connection.select_lyric_by_id(&id).unwrap().unwrap();
-//         assert!(stored_lyric.created.is_some());
-//         assert!(stored_lyric.modified.is_some());
-//         assert!(stored_lyric.etag.is_some());
-
-//         "Hallo allemaal".clone_into(&mut lyric.title);
-//         thread::sleep(Duration::from_millis(5));
-//         if connection.update_lyric(&lyric).unwrap() {
-//             let lyric = connection.select_lyric_by_id(&id).unwrap().unwrap();
-//             println!("created: {}", lyric.created.unwrap());
-//             println!("modified: {}", lyric.modified.unwrap());
-//         };
-//     }
-
-//     #[test]
-//     fn insert_playlist() {
-//         let connection = open();
-
-//         let lyric_id = Uuid::default().to_string();
-//         let lyric = Lyric::new(
-//             lyric_id.clone(),
-//             "Zie maar hoe je het doet".to_owned(),
-//             vec![],
-//         );
-
-//         assert!(lyric.created.is_none());
-//         assert!(lyric.modified.is_none());
-//         assert!(lyric.etag.is_none());
-
-//         connection.insert_lyric(&lyric).unwrap();
-
-//         let playlist_id = Uuid::default().to_string();
-//         let playlist = Playlist::new(
-//             playlist_id.clone(),
-//             "Alles".to_owned(),
-//             vec![lyric_id.clone()],
-//         );
-//         connection.insert_playlist(&playlist, false).unwrap();
-
-//         let stored_playlist = connection
-//             .select_playlist_by_id(&playlist_id)
-//             .unwrap()
-//             .unwrap();
-//         assert!(stored_playlist.created.is_some());
-//         assert!(stored_playlist.modified.is_some());
-//         assert!(stored_playlist.etag.is_some());
-
-//         connection.delete_lyric(&lyric_id).unwrap();
-
-//         let without_lyric = connection
-//             .select_playlist_by_id(&playlist_id)
-//             .unwrap()
-//             .unwrap();
-//         assert!(without_lyric.members.is_empty());
-//     }
-
-//     #[test]
-//     fn show_tables() {
-//         let connection = open();
-
-//         struct Table {
-//             name: String,
-//         }
-
-//         impl TryFrom<Row<'_>> for Table {
-//             type Error = Error;
-
-//             fn try_from(row: Row<'_>) -> Result<Self, Self::Error> {
-//                 row.get::<&str>("name")
-//                     .ok_or(Error::MissingColumn("name"))
-//                     .map(String::from)
-//                     .map(|s| Self { name: s })
-//             }
-//         }
-
-//         let tables = connection
-//             .0
-//             .query::<Table>(
-//                 "SELECT name FROM sqlite_schema WHERE type ='table' AND  name NOT LIKE 'sqlite_%';",
-//                 &[],
-//             )
-//             .unwrap();
-
-//         let mut names = tables
-//             .into_iter()
-//             .map(|table| table.name)
-//             .collect::<Vec<_>>();
-//         names.sort();
-//         assert_eq!(
-//             names,
-//             vec![
-//                 "list_etag".to_owned(),
-//                 "lyric".to_owned(),
-//                 "member".to_owned(),
-//                 "playlist".to_owned(),
-//                 "user".to_owned(),
-//             ]
-//         );
-//     }
-
-//     #[test]
-//     fn import_db() {
-//         let connection = open();
-
-//         let db = Db::try_from_json(include_bytes!("../data/db.json")).unwrap();
-//         connection.replace_db(&db).unwrap();
-
-//         let lyrics = connection.select_lyric().unwrap();
-//         assert_eq!(lyrics.len(), 59);
-
-//         let playlists = connection.select_playlist().unwrap();
-//         assert_eq!(playlists.len(), 2);
-//     }
-
-//     #[test]
-//     fn valid_user() {
-//         let connection = open();
-//         let is_valid = connection.is_valid_user("paul", "password").unwrap();
-//         assert!(is_valid);
-//     }
-// }
@@ -0,0 +1,1074 @@
+//! Lyrics, playlists and tags as json documents in a key-value store, for hosts without sqlite.
+//!
+//! Every document has its own key, like `lyric/{id}`, and the list etags are kept in `etag/lyrics`
+//! and `etag/playlists`. A key-value store has no transactions, so the checks are done before
+//! anything is written, but a write that fails halfway is not undone.
+
+use chrono::{DateTime, Utc};
+use futures::{FutureExt, Stream, future::BoxFuture, stream};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use spin_sdk::key_value::Store;
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use super::valid;
+use crate::{context::Context, message};
+use model::{
+    Db, Lyric, LyricQuery, MemberMove, MemberPost, Playlist, Tag, TagPost, Tenant, User, Uuid,
+    error::Error,
+    member::Member,
+    patch::{Patch, patched},
+    validation::{Validate, Violation, member_move_violations},
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+const LYRIC: &str = "lyric/";
+const PLAYLIST: &str = "playlist/";
+const TAG: &str = "tag/";
+const TENANT: &str = "tenant/";
+const USER: &str = "user/";
+const LYRIC_LIST_ETAG: &str = "etag/lyrics";
+const PLAYLIST_LIST_ETAG: &str = "etag/playlists";
+
+/// Labels of the stores this instance already gave a user
+static SEEDED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn key(prefix: &str, id: &str) -> String {
+    format!("{prefix}{id}")
+}
+
+/// The keys and values of a [`Connection`], implemented for the key-value store of the Spin host
+pub trait KeyValueStore: Send + Sync {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>>;
+    fn set<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, Result<()>>;
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>>;
+    fn keys(&self) -> BoxFuture<'_, Result<Vec<String>>>;
+}
+
+impl KeyValueStore for Store {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        async move { Ok(Store::get(self, key).await?) }.boxed()
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move { Ok(Store::set(self, key, value).await?) }.boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move { Ok(Store::delete(self, key).await?) }.boxed()
+    }
+
+    fn keys(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move { Ok(self.get_keys().await.collect().await?) }.boxed()
+    }
+}
+
+/// A store shared by the connections of several requests
+impl<T: KeyValueStore + ?Sized> KeyValueStore for Arc<T> {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        (**self).get(key)
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        (**self).set(key, value)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        (**self).delete(key)
+    }
+
+    fn keys(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        (**self).keys()
+    }
+}
+
+/// The keys of a tenant in a store shared with other tenants, all starting with `prefix`
+pub struct Prefixed<T> {
+    pub store: T,
+    pub prefix: String,
+}
+
+impl<T> Prefixed<T> {
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+}
+
+impl<T: KeyValueStore> KeyValueStore for Prefixed<T> {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        async move { self.store.get(&self.key(key)).await }.boxed()
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        async move { self.store.set(&self.key(key), value).await }.boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        async move { self.store.delete(&self.key(key)).await }.boxed()
+    }
+
+    fn keys(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        async move {
+            self.store.keys().await.map(|keys| {
+                keys.into_iter()
+                    .filter_map(|key| key.strip_prefix(&self.prefix).map(String::from))
+                    .collect()
+            })
+        }
+        .boxed()
+    }
+}
+
+/// The timestamps and etag, which are not part of the json representation of a lyric or playlist
+#[derive(Clone, Default, Deserialize, Serialize)]
+struct Stamps {
+    created: Option<DateTime<Utc>>,
+    modified: Option<DateTime<Utc>>,
+    etag: Option<Uuid>,
+}
+
+impl Stamps {
+    fn new() -> Self {
+        let now = Utc::now();
+        Self {
+            created: Some(now),
+            modified: Some(now),
+            etag: Some(Uuid::default()),
+        }
+    }
+
+    /// Modified now with a new etag, created stays the same
+    fn touched(self) -> Self {
+        Self {
+            modified: Some(Utc::now()),
+            etag: Some(Uuid::default()),
+            ..self
+        }
+    }
+}
+
+trait Stamped: Serialize + DeserializeOwned {
+    fn stamps(&self) -> Stamps;
+    fn stamped(self, stamps: Stamps) -> Self;
+}
+
+impl Stamped for Lyric {
+    fn stamps(&self) -> Stamps {
+        Stamps {
+            created: self.created,
+            modified: self.modified,
+            etag: self.etag.clone(),
+        }
+    }
+
+    fn stamped(self, stamps: Stamps) -> Self {
+        Self {
+            created: stamps.created,
+            modified: stamps.modified,
+            etag: stamps.etag,
+            ..self
+        }
+    }
+}
+
+impl Stamped for Playlist {
+    fn stamps(&self) -> Stamps {
+        Stamps {
+            created: self.created,
+            modified: self.modified,
+            etag: self.etag.clone(),
+        }
+    }
+
+    fn stamped(self, stamps: Stamps) -> Self {
+        Self {
+            created: stamps.created,
+            modified: stamps.modified,
+            etag: stamps.etag,
+            ..self
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Document<T> {
+    value: T,
+    #[serde(flatten)]
+    stamps: Stamps,
+}
+
+/// The password of a user is left out of its json representation
+#[derive(Deserialize, Serialize)]
+struct StoredUser {
+    id: String,
+    name: String,
+    password: String,
+}
+
+impl From<StoredUser> for User {
+    fn from(user: StoredUser) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            password: user.password,
+        }
+    }
+}
+
+/// The password of a tenant is left out of its json representation as well
+#[derive(Deserialize, Serialize)]
+struct StoredTenant {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+impl From<StoredTenant> for Tenant {
+    fn from(tenant: StoredTenant) -> Self {
+        Self {
+            name: tenant.name,
+            host: tenant.host,
+            username: tenant.username,
+            password: tenant.password,
+        }
+    }
+}
+
+impl From<&Tenant> for StoredTenant {
+    fn from(tenant: &Tenant) -> Self {
+        Self {
+            name: tenant.name.clone(),
+            host: tenant.host.clone(),
+            username: tenant.username.clone(),
+            password: tenant.password.clone(),
+        }
+    }
+}
+
+fn same(filter: Option<&String>, value: Option<&String>) -> bool {
+    filter.is_none_or(|filter| value.is_some_and(|value| value.eq_ignore_ascii_case(filter)))
+}
+
+/// The same filter as the sqlite query, text compared case insensitive and all tags present
+fn matches(query: &LyricQuery, lyric: &Lyric) -> bool {
+    same(query.artist.as_ref(), lyric.meta.artist.as_ref())
+        && same(query.composer.as_ref(), lyric.meta.composer.as_ref())
+        && same(query.key.as_ref(), lyric.meta.key.as_ref())
+        && same(query.language.as_ref(), lyric.meta.language.as_ref())
+        && query.tags.iter().all(|tag| lyric.tags.contains(tag))
+}
+
+fn id_in_use() -> Error {
+    Error::Validation(vec![Violation::new("id", "already in use")])
+}
+
+pub struct Connection(Box<dyn KeyValueStore>);
+
+impl Connection {
+    /// The key-value store of the Spin host with `label`
+    pub async fn open(label: &str, context: &Context) -> Result<Self> {
+        let store = Store::open(label).await?;
+        message::db_connection_established(context, "key-value store", label);
+        Ok(Self::new(store))
+    }
+
+    pub fn new(store: impl KeyValueStore + 'static) -> Self {
+        Self(Box::new(store))
+    }
+
+    /// Gives the store of the Spin host with `label` a user with `name` and `password` when it has no users yet,
+    /// like the migrations do for a sqlite database. Only checked on the first call for a store by this instance.
+    pub async fn seed(label: &str, context: &Context, name: &str, password: &str) -> Result<()> {
+        let seeded = SEEDED
+            .lock()
+            .map(|labels| labels.contains(label))
+            .unwrap_or_default();
+        if !seeded {
+            Self::open(label, context)
+                .await?
+                .seed_user(name, password)
+                .await?;
+            if let Ok(mut labels) = SEEDED.lock() {
+                labels.insert(label.to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a user with `name` and `password` when there are no users
+    pub async fn seed_user(&self, name: &str, password: &str) -> Result<()> {
+        if !self.keys(USER).await?.is_empty() {
+            return Ok(());
+        }
+        let user = StoredUser {
+            id: Uuid::default().to_string(),
+            name: name.to_owned(),
+            password: password.to_owned(),
+        };
+        self.set(&key(USER, &user.id), &user).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.0.get(key).await? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        self.0.set(key, serde_json::to_vec(value)?).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        self.0.get(key).await.map(|value| value.is_some())
+    }
+
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        self.0.keys().await.map(|keys| {
+            keys.into_iter()
+                .filter(|key| key.starts_with(prefix))
+                .collect()
+        })
+    }
+
+    async fn all<T: DeserializeOwned>(&self, prefix: &str) -> Result<Vec<T>> {
+        let mut values = vec![];
+        for key in self.keys(prefix).await? {
+            values.extend(self.get(&key).await?);
+        }
+        Ok(values)
+    }
+
+    async fn delete_all(&self, prefix: &str) -> Result<()> {
+        for key in self.keys(prefix).await? {
+            self.0.delete(&key).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_document<T: Stamped>(&self, key: &str) -> Result<Option<T>> {
+        self.get::<Document<T>>(key)
+            .await
+            .map(|document| document.map(|document| document.value.stamped(document.stamps)))
+    }
+
+    async fn set_document<T: Stamped>(&self, key: &str, value: &T) -> Result<()> {
+        self.set(
+            key,
+            &Document {
+                value,
+                stamps: value.stamps(),
+            },
+        )
+        .await
+    }
+
+    async fn all_documents<T: Stamped>(&self, prefix: &str) -> Result<Vec<T>> {
+        self.all::<Document<T>>(prefix).await.map(|documents| {
+            documents
+                .into_iter()
+                .map(|document| document.value.stamped(document.stamps))
+                .collect()
+        })
+    }
+
+    pub async fn select_user(&self) -> Result<Vec<User>> {
+        let mut users = self
+            .all::<StoredUser>(USER)
+            .await?
+            .into_iter()
+            .map(User::from)
+            .collect::<Vec<_>>();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(users)
+    }
+
+    pub async fn select_tenant(&self) -> Result<Vec<Tenant>> {
+        let mut tenants = self
+            .all::<StoredTenant>(TENANT)
+            .await?
+            .into_iter()
+            .map(Tenant::from)
+            .collect::<Vec<_>>();
+        tenants.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tenants)
+    }
+
+    pub async fn select_tenant_by_name(&self, name: &str) -> Result<Option<Tenant>> {
+        self.get::<StoredTenant>(&key(TENANT, name))
+            .await
+            .map(|tenant| tenant.map(Tenant::from))
+    }
+
+    pub async fn select_tenant_by_host(&self, host: &str) -> Result<Option<Tenant>> {
+        Ok(self
+            .all::<StoredTenant>(TENANT)
+            .await?
+            .into_iter()
+            .map(Tenant::from)
+            .find(|tenant| {
+                tenant
+                    .host
+                    .as_ref()
+                    .is_some_and(|h| h.eq_ignore_ascii_case(host))
+            }))
+    }
+
+    pub async fn insert_tenant(&self, tenant: &Tenant) -> Result<()> {
+        let mut violations = tenant.violations();
+        if self.exists(&key(TENANT, &tenant.name)).await? {
+            violations.push(Violation::new("name", "already in use"));
+        }
+        if let Some(host) = &tenant.host
+            && self.select_tenant_by_host(host).await?.is_some()
+        {
+            violations.push(Violation::new("host", "already in use"));
+        }
+        valid(violations)?;
+        self.set(&key(TENANT, &tenant.name), &StoredTenant::from(tenant))
+            .await
+    }
+
+    /// The data of the tenant is left alone
+    pub async fn delete_tenant(&self, name: &str) -> Result<bool> {
+        if !self.exists(&key(TENANT, name)).await? {
+            return Ok(false);
+        }
+        self.0.delete(&key(TENANT, name)).await.map(|_| true)
+    }
+
+    pub async fn select_lyric(&self) -> Result<Vec<Lyric>> {
+        self.select_lyric_by_query(&LyricQuery::default()).await
+    }
+
+    /// All lyrics, read before the first one is returned
+    pub async fn stream_lyric(&self) -> Result<impl Stream<Item = Result<Lyric>> + use<>> {
+        Ok(stream::iter(self.select_lyric().await?.into_iter().map(Ok)))
+    }
+
+    pub async fn select_lyric_by_query(&self, query: &LyricQuery) -> Result<Vec<Lyric>> {
+        let mut lyrics = self
+            .all_documents::<Lyric>(LYRIC)
+            .await?
+            .into_iter()
+            .filter(|lyric| matches(query, lyric))
+            .collect::<Vec<_>>();
+        lyrics.sort_by(|a, b| a.title.cmp(&b.title));
+        Ok(lyrics)
+    }
+
+    pub async fn select_lyric_by_id(&self, id: &str) -> Result<Option<Lyric>> {
+        self.get_document(&key(LYRIC, id)).await
+    }
+
+    /// The lyrics with one of the ids, every lyric is a document of its own
+    pub async fn select_lyric_by_ids(&self, ids: &[&str]) -> Result<Vec<Lyric>> {
+        let mut lyrics = vec![];
+        for id in ids {
+            lyrics.extend(self.select_lyric_by_id(id).await?);
+        }
+        Ok(lyrics)
+    }
+
+    /// Removes the lyric from the playlists as well
+    /// The playlists with the lyric as member lose it, and get a new etag
+    pub async fn delete_lyric(&self, id: &str) -> Result<bool> {
+        if !self.exists(&key(LYRIC, id)).await? {
+            return Ok(false);
+        }
+        self.0.delete(&key(LYRIC, id)).await?;
+        for mut playlist in self.all_documents::<Playlist>(PLAYLIST).await? {
+            let count = playlist.members.len();
+            playlist.members.retain(|member| member.lyric_id != id);
+            if playlist.members.len() != count {
+                self.touch_playlist(playlist).await?;
+            }
+        }
+        Ok(true)
+    }
+
+    pub async fn update_lyric(&self, lyric: &Lyric) -> Result<bool> {
+        self.check_lyric(lyric).await?;
+        match self.select_lyric_by_id(&lyric.id).await? {
+            Some(stored) if lyric.chords.is_none() => {
+                let lyric = lyric.clone().keep_chords(&stored);
+                self.rewrite_lyric(&lyric, &stored).await.map(|_| true)
+            }
+            Some(stored) => self.rewrite_lyric(lyric, &stored).await.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Keeps the created timestamp and etag of the stored lyric
+    async fn rewrite_lyric(&self, lyric: &Lyric, stored: &Lyric) -> Result<()> {
+        let stamps = Stamps {
+            modified: Some(Utc::now()),
+            ..stored.stamps()
+        };
+        let lyric = Lyric {
+            tags: self.write_tags(&lyric.tags).await?,
+            ..lyric.clone()
+        }
+        .stamped(stamps);
+        self.set_document(&key(LYRIC, &lyric.id), &lyric).await
+    }
+
+    pub async fn insert_lyric(&self, lyric: &Lyric) -> Result<()> {
+        self.check_lyric(lyric).await?;
+        self.write_lyric(lyric).await
+    }
+
+    /// Checks all lyrics before the first one is written,
+    /// violations are reported per lyric like `lyrics[1].title`
+    pub async fn insert_lyrics(&self, lyrics: &[Lyric]) -> Result<()> {
+        let db = Db {
+            lyrics: lyrics.to_vec(),
+            playlists: vec![],
+        };
+        let mut violations = db.violations();
+        for (i, lyric) in lyrics.iter().enumerate() {
+            if self.lyric_title_in_use(&lyric.title, &lyric.id).await? {
+                violations.push(Violation::duplicate_title(format!("lyrics[{i}].title")));
+            }
+        }
+        valid(violations)?;
+        for lyric in lyrics {
+            self.write_lyric(lyric).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_lyric(&self, lyric: &Lyric) -> Result<()> {
+        if self.exists(&key(LYRIC, &lyric.id)).await? {
+            return Err(id_in_use());
+        }
+        self.store_lyric(lyric).await
+    }
+
+    /// Writes a lyric with new stamps, replacing a stored one with the same id
+    async fn store_lyric(&self, lyric: &Lyric) -> Result<()> {
+        let lyric = Lyric {
+            tags: self.write_tags(&lyric.tags).await?,
+            ..lyric.clone()
+        }
+        .stamped(Stamps::new());
+        self.set_document(&key(LYRIC, &lyric.id), &lyric).await
+    }
+
+    /// The tag names of a lyric in order, creating the tags that do not exist yet
+    async fn write_tags(&self, tags: &[String]) -> Result<Vec<String>> {
+        let mut names = tags
+            .iter()
+            .map(|tag| Tag::normalize(tag))
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let existing = self.all::<Tag>(TAG).await?;
+        for name in names.iter() {
+            if !existing.iter().any(|tag| &tag.name == name) {
+                let tag = Tag {
+                    id: Uuid::default().to_string(),
+                    name: name.clone(),
+                    count: 0,
+                };
+                self.set(&key(TAG, &tag.id), &tag).await?;
+            }
+        }
+        Ok(names)
+    }
+
+    /// The tags with the number of lyrics that have them
+    async fn counted(&self, tags: Vec<Tag>) -> Result<Vec<Tag>> {
+        let lyrics = self.all_documents::<Lyric>(LYRIC).await?;
+        Ok(tags
+            .into_iter()
+            .map(|tag| Tag {
+                count: lyrics
+                    .iter()
+                    .filter(|lyric| lyric.tags.contains(&tag.name))
+                    .count()
+                    .try_into()
+                    .unwrap_or(u32::MAX),
+                ..tag
+            })
+            .collect())
+    }
+
+    pub async fn select_tag(&self) -> Result<Vec<Tag>> {
+        let mut tags = self.counted(self.all::<Tag>(TAG).await?).await?;
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    pub async fn select_tag_by_id(&self, id: &str) -> Result<Option<Tag>> {
+        match self.get::<Tag>(&key(TAG, id)).await? {
+            Some(tag) => self
+                .counted(vec![tag])
+                .await
+                .map(|tags| tags.into_iter().next()),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn insert_tag(&self, tag: &Tag) -> Result<()> {
+        let name = Tag::normalize(&tag.name);
+        self.check_tag(&tag.id, &name).await?;
+        if self.exists(&key(TAG, &tag.id)).await? {
+            return Err(id_in_use());
+        }
+        self.set(
+            &key(TAG, &tag.id),
+            &Tag {
+                id: tag.id.clone(),
+                name,
+                count: 0,
+            },
+        )
+        .await
+    }
+
+    /// Renames the tag of the lyrics as well
+    pub async fn update_tag(&self, id: &str, tag: &TagPost) -> Result<bool> {
+        let name = Tag::normalize(&tag.name);
+        self.check_tag(id, &name).await?;
+        let Some(stored) = self.get::<Tag>(&key(TAG, id)).await? else {
+            return Ok(false);
+        };
+        self.set(
+            &key(TAG, id),
+            &Tag {
+                name: name.clone(),
+                ..stored.clone()
+            },
+        )
+        .await?;
+        self.retag_lyrics(&stored.name, Some(&name)).await?;
+        Ok(true)
+    }
+
+    /// Removes the tag from the lyrics as well
+    pub async fn delete_tag(&self, id: &str) -> Result<bool> {
+        let Some(stored) = self.get::<Tag>(&key(TAG, id)).await? else {
+            return Ok(false);
+        };
+        self.0.delete(&key(TAG, id)).await?;
+        self.retag_lyrics(&stored.name, None).await?;
+        Ok(true)
+    }
+
+    /// Replaces or removes the tag `name` of the lyrics that have it
+    async fn retag_lyrics(&self, name: &str, replacement: Option<&String>) -> Result<()> {
+        for mut lyric in self.all_documents::<Lyric>(LYRIC).await? {
+            if lyric.tags.iter().any(|tag| tag == name) {
+                lyric.tags.retain(|tag| tag != name);
+                lyric.tags.extend(replacement.cloned());
+                lyric.tags.sort();
+                self.set_document(&key(LYRIC, &lyric.id), &lyric).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_tag(&self, id: &str, name: &str) -> Result<()> {
+        let mut violations = TagPost {
+            name: name.to_owned(),
+        }
+        .violations();
+        if self
+            .all::<Tag>(TAG)
+            .await?
+            .iter()
+            .any(|tag| tag.name == name && tag.id != id)
+        {
+            violations.push(Violation::new("name", "already in use"));
+        }
+        valid(violations)
+    }
+
+    pub async fn select_playlist(&self) -> Result<Vec<Playlist>> {
+        let mut playlists = self.all_documents::<Playlist>(PLAYLIST).await?;
+        playlists.sort_by(|a, b| a.title.cmp(&b.title));
+        Ok(playlists)
+    }
+
+    /// All playlists, read before the first one is returned
+    pub async fn stream_playlist(self) -> Result<impl Stream<Item = Result<Playlist>> + 'static> {
+        Ok(stream::iter(
+            self.select_playlist().await?.into_iter().map(Ok),
+        ))
+    }
+
+    pub async fn select_playlist_by_id(&self, id: &str) -> Result<Option<Playlist>> {
+        self.get_document(&key(PLAYLIST, id)).await
+    }
+
+    pub async fn delete_playlist_by_id(&self, id: &str) -> Result<()> {
+        self.0.delete(&key(PLAYLIST, id)).await
+    }
+
+    pub async fn delete_members_by_playlist_id(&self, playlist_id: &str) -> Result<i64> {
+        if let Some(playlist) = self.select_playlist_by_id(playlist_id).await? {
+            self.set_document(
+                &key(PLAYLIST, playlist_id),
+                &Playlist {
+                    members: vec![],
+                    ..playlist
+                },
+            )
+            .await?;
+        }
+        Ok(0)
+    }
+
+    pub async fn insert_members(&self, playlist_id: &str, members: &[Member]) -> Result<()> {
+        if let Some(mut playlist) = self.select_playlist_by_id(playlist_id).await? {
+            playlist.members.extend_from_slice(members);
+            self.set_document(&key(PLAYLIST, playlist_id), &playlist)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn update_playlist(&self, playlist: &Playlist) -> Result<()> {
+        self.check_playlist(playlist).await?;
+        self.rewrite_playlist(playlist).await
+    }
+
+    /// Keeps the created timestamp of the stored playlist
+    async fn rewrite_playlist(&self, playlist: &Playlist) -> Result<()> {
+        let stored = self
+            .select_playlist_by_id(&playlist.id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        let playlist = playlist.clone().stamped(stored.stamps().touched());
+        self.set_document(&key(PLAYLIST, &playlist.id), &playlist)
+            .await
+    }
+
+    /// Returns false if the lyric does not exist
+    pub async fn patch_lyric(
+        &self,
+        id: &str,
+        if_match: Option<&str>,
+        patch: &Patch,
+    ) -> Result<bool> {
+        let Some(stored) = self.select_lyric_by_id(id).await? else {
+            return Ok(false);
+        };
+        let lyric = patched(stored.clone(), |l| &l.id, if_match, patch)?;
+        self.check_lyric(&lyric).await?;
+        self.rewrite_lyric(&lyric, &stored).await.map(|_| true)
+    }
+
+    /// Returns false if the playlist does not exist
+    pub async fn patch_playlist(
+        &self,
+        id: &str,
+        if_match: Option<&str>,
+        patch: &Patch,
+    ) -> Result<bool> {
+        let Some(playlist) = self.select_playlist_by_id(id).await? else {
+            return Ok(false);
+        };
+        let playlist = patched(playlist, |p| &p.id, if_match, patch)?;
+        self.check_playlist(&playlist).await?;
+        self.rewrite_playlist(&playlist).await.map(|_| true)
+    }
+
+    pub async fn insert_playlist(&self, playlist: &Playlist) -> Result<()> {
+        self.check_playlist(playlist).await?;
+        self.write_playlist(playlist).await
+    }
+
+    async fn write_playlist(&self, playlist: &Playlist) -> Result<()> {
+        if self.exists(&key(PLAYLIST, &playlist.id)).await? {
+            return Err(id_in_use());
+        }
+        self.store_playlist(playlist).await
+    }
+
+    /// Writes a playlist with new stamps, replacing a stored one with the same id
+    async fn store_playlist(&self, playlist: &Playlist) -> Result<()> {
+        let playlist = playlist.clone().stamped(Stamps::new());
+        self.set_document(&key(PLAYLIST, &playlist.id), &playlist)
+            .await
+    }
+
+    async fn lyric_title_in_use(&self, title: &str, id: &str) -> Result<bool> {
+        Ok(self
+            .all_documents::<Lyric>(LYRIC)
+            .await?
+            .iter()
+            .any(|lyric| lyric.title == title && lyric.id != id))
+    }
+
+    /// Fails with the violations found, including titles already used by another lyric
+    async fn check_lyric(&self, lyric: &Lyric) -> Result<()> {
+        let mut violations = lyric.violations();
+        if self.lyric_title_in_use(&lyric.title, &lyric.id).await? {
+            violations.push(Violation::duplicate_title("title"));
+        }
+        valid(violations)
+    }
+
+    /// Fails with the violations found, including unknown lyrics and titles already used by another playlist
+    async fn check_playlist(&self, playlist: &Playlist) -> Result<()> {
+        let mut violations = playlist.violations();
+        for (i, member) in playlist.members.iter().enumerate() {
+            if !self.exists(&key(LYRIC, &member.lyric_id)).await? {
+                violations.push(Violation::unknown_lyric(format!("members[{i}].lyric_id")));
+            }
+        }
+        if self
+            .select_playlist()
+            .await?
+            .iter()
+            .any(|p| p.title == playlist.title && p.id != playlist.id)
+        {
+            violations.push(Violation::duplicate_title("title"));
+        }
+        valid(violations)
+    }
+
+    async fn check_member(&self, member: &Member) -> Result<()> {
+        let mut violations = member.violations();
+        if !self.exists(&key(LYRIC, &member.lyric_id)).await? {
+            violations.push(Violation::unknown_lyric("lyric_id"));
+        }
+        valid(violations)
+    }
+
+    /// Stores the changed members with a new etag for the playlist and for the list of playlists
+    async fn touch_playlist(&self, playlist: Playlist) -> Result<()> {
+        let playlist = Playlist {
+            modified: Some(Utc::now()),
+            etag: Some(Uuid::default()),
+            ..playlist
+        };
+        self.set_document(&key(PLAYLIST, &playlist.id), &playlist)
+            .await?;
+        self.update_playlist_list_etag().await
+    }
+
+    /// Returns false if the playlist does not exist
+    pub async fn insert_member(&self, playlist_id: &str, member: &MemberPost) -> Result<bool> {
+        let Some(mut playlist) = self.select_playlist_by_id(playlist_id).await? else {
+            return Ok(false);
+        };
+        let count = playlist.members.len();
+        let position = member.position.unwrap_or(count);
+        if position > count {
+            return Err(Error::Validation(vec![Violation::new(
+                "position",
+                format!("must be at most {count}"),
+            )]));
+        }
+        self.check_member(&member.member).await?;
+        playlist.members.insert(position, member.member.clone());
+        self.touch_playlist(playlist).await.map(|_| true)
+    }
+
+    /// Returns false if the playlist does not exist or has no member at `position`
+    pub async fn delete_member(&self, playlist_id: &str, position: usize) -> Result<bool> {
+        match self.select_playlist_by_id(playlist_id).await? {
+            Some(mut playlist) if position < playlist.members.len() => {
+                playlist.members.remove(position);
+                self.touch_playlist(playlist).await.map(|_| true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Applies the moves in order. Returns false if the playlist does not exist
+    pub async fn move_members(&self, playlist_id: &str, moves: &[MemberMove]) -> Result<bool> {
+        let Some(mut playlist) = self.select_playlist_by_id(playlist_id).await? else {
+            return Ok(false);
+        };
+        let count = playlist.members.len();
+        valid(member_move_violations(moves, count))?;
+        for MemberMove { from, to } in moves {
+            let member = playlist.members.remove(*from);
+            playlist.members.insert(*to, member);
+        }
+        self.touch_playlist(playlist).await.map(|_| true)
+    }
+
+    pub async fn update_lyric_list_etag(&self) -> Result<()> {
+        self.set(LYRIC_LIST_ETAG, &Uuid::default()).await
+    }
+
+    pub async fn update_playlist_list_etag(&self) -> Result<()> {
+        self.set(PLAYLIST_LIST_ETAG, &Uuid::default()).await
+    }
+
+    pub async fn delete_all_lyrics(&self) -> Result<()> {
+        self.delete_all(LYRIC).await
+    }
+
+    pub async fn delete_all_playlists(&self) -> Result<()> {
+        self.delete_all(PLAYLIST).await
+    }
+
+    pub async fn delete_all_members(&self) -> Result<()> {
+        for playlist in self.all_documents::<Playlist>(PLAYLIST).await? {
+            self.delete_members_by_playlist_id(&playlist.id).await?;
+        }
+        Ok(())
+    }
+
+    /// The store has no transactions, so the new documents are written first and the old ones
+    /// that were not replaced are deleted last. A failure halfway leaves all old data in place.
+    pub async fn replace_db(&self, db: &Db) -> Result<()> {
+        valid(db.violations())?;
+        let old_playlists = self.keys(PLAYLIST).await?;
+        let old_lyrics = self.keys(LYRIC).await?;
+        for lyric in db.lyrics.iter() {
+            self.store_lyric(lyric).await?;
+        }
+        for playlist in db.playlists.iter() {
+            self.store_playlist(playlist).await?;
+        }
+        let new_playlists = db
+            .playlists
+            .iter()
+            .map(|playlist| key(PLAYLIST, &playlist.id))
+            .collect::<Vec<_>>();
+        let new_lyrics = db
+            .lyrics
+            .iter()
+            .map(|lyric| key(LYRIC, &lyric.id))
+            .collect::<Vec<_>>();
+        for old in old_playlists
+            .iter()
+            .filter(|old| !new_playlists.contains(old))
+        {
+            self.0.delete(old).await?;
+        }
+        for old in old_lyrics.iter().filter(|old| !new_lyrics.contains(old)) {
+            self.0.delete(old).await?;
+        }
+        self.update_lyric_list_etag().await?;
+        self.update_playlist_list_etag().await
+    }
+}
+
+/// Keys and values in memory, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore(std::sync::Mutex<std::collections::BTreeMap<String, Vec<u8>>>);
+
+#[cfg(test)]
+impl MemoryStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, std::collections::BTreeMap<String, Vec<u8>>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+impl KeyValueStore for MemoryStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>>> {
+        futures::future::ready(Ok(self.lock().get(key).cloned())).boxed()
+    }
+
+    fn set<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        self.lock().insert(key.to_owned(), value);
+        futures::future::ready(Ok(())).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<()>> {
+        self.lock().remove(key);
+        futures::future::ready(Ok(())).boxed()
+    }
+
+    fn keys(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        futures::future::ready(Ok(self.lock().keys().cloned().collect())).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use model::{Lyric, LyricQuery, Playlist, Uuid};
+
+    use super::{Connection, MemoryStore};
+
+    fn lyric(title: &str, tags: &[&str]) -> Lyric {
+        Lyric {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Lyric::new(Uuid::default().to_string(), title.to_owned(), vec![])
+        }
+    }
+
+    #[test]
+    fn lyric_tags() {
+        block_on(async {
+            let connection = Connection::new(MemoryStore::default());
+            let sofietje = lyric("Sofietje", &["Kerst ", "kerst", "pasen"]);
+            connection.insert_lyric(&sofietje).await.unwrap();
+            connection
+                .insert_lyric(&lyric("Dodenrit", &["pasen"]))
+                .await
+                .unwrap();
+            assert!(connection.insert_lyric(&sofietje).await.is_err());
+
+            let stored = connection
+                .select_lyric_by_id(&sofietje.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored.tags, vec!["kerst", "pasen"]);
+            assert!(stored.etag.is_some());
+
+            let query = LyricQuery {
+                tags: vec!["kerst".to_owned(), "pasen".to_owned()],
+                ..LyricQuery::default()
+            };
+            let found = connection.select_lyric_by_query(&query).await.unwrap();
+            assert_eq!(found.len(), 1);
+            let counts = connection
+                .select_tag()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|tag| (tag.name, tag.count))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                counts,
+                vec![("kerst".to_owned(), 1), ("pasen".to_owned(), 2)]
+            );
+        });
+    }
+
+    #[test]
+    fn deleted_lyric_leaves_playlists() {
+        block_on(async {
+            let connection = Connection::new(MemoryStore::default());
+            let lyric = lyric("Sofietje", &[]);
+            connection.insert_lyric(&lyric).await.unwrap();
+            let playlist = Playlist::new(
+                Uuid::default().to_string(),
+                "Alles".to_owned(),
+                vec![lyric.id.as_str().into()],
+            );
+            connection.insert_playlist(&playlist).await.unwrap();
+
+            assert!(connection.delete_lyric(&lyric.id).await.unwrap());
+            assert!(!connection.delete_lyric(&lyric.id).await.unwrap());
+            let stored = connection
+                .select_playlist_by_id(&playlist.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(stored.members.is_empty());
+        });
+    }
+}
@@ -0,0 +1,1364 @@
+use futures::{Stream, StreamExt, stream::BoxStream};
+use spin_sdk::sqlite::Value;
+use spin_sqlite_connection::{FromRow, Params, SqliteConnection, Storage, named_params, params};
+use std::{
+    collections::{BTreeSet, HashSet},
+    pin::Pin,
+    sync::Mutex,
+    task::{Context as TaskContext, Poll},
+};
+
+use super::valid;
+use crate::{context::Context, message};
+use model::{
+    Db, Lyric, LyricId, LyricMeta, LyricQuery, MemberMove, MemberPost, Playlist, Tag, TagPost,
+    Tenant, User, Uuid,
+    error::Error,
+    member::Member,
+    patch::{Patch, patched},
+    validation::{Validate, Violation, member_move_violations},
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn first<T: Clone>(list: Vec<T>) -> Option<T> {
+    list.first().cloned()
+}
+
+fn unit<T>(_: T) {}
+
+fn json_array<T: serde::Serialize>(values: &[T]) -> Result<Value> {
+    serde_json::to_string(values)
+        .map(Value::Text)
+        .map_err(Error::from)
+}
+
+/// The update leaves the etag alone, a named parameter missing from the statement is not bound
+fn lyric_params(lyric: &Lyric) -> Params {
+    let LyricMeta {
+        artist,
+        composer,
+        copyright,
+        key,
+        tempo,
+        language,
+        duration,
+        notes,
+    } = &lyric.meta;
+    named_params! {
+        ":id": lyric.id,
+        ":title": lyric.title,
+        ":parts": lyric.to_parts().to_text(),
+        ":etag": Uuid::default(),
+        ":artist": artist,
+        ":composer": composer,
+        ":copyright": copyright,
+        ":key": key,
+        ":tempo": tempo,
+        ":language": language,
+        ":duration": duration,
+        ":notes": notes,
+    }
+}
+
+fn lyric_query_params(query: &LyricQuery) -> Result<Params> {
+    Ok(named_params! {
+        ":artist": query.artist,
+        ":composer": query.composer,
+        ":key": query.key,
+        ":language": query.language,
+        ":tags": json_array(&query.tags)?,
+    })
+}
+
+fn member_params(playlist_id: &str, member: &Member, ordering: i64) -> Params {
+    named_params! {
+        ":playlist_id": playlist_id,
+        ":lyric_id": member.lyric_id,
+        ":ordering": ordering,
+        ":key": member.details.key,
+        ":repeat": member.details.repeat,
+        ":notes": member.details.notes,
+        ":segment": member.details.segment,
+    }
+}
+
+/// Member ordering in the database is one based
+fn ordering(position: usize) -> i64 {
+    offset(position) + 1
+}
+
+fn offset(position: usize) -> i64 {
+    i64::try_from(position).unwrap_or(i64::MAX - 1)
+}
+
+#[derive(FromRow)]
+#[row(error = "Error")]
+struct MemberOrdering(#[row(column = "ordering")] i64);
+
+#[derive(FromRow)]
+#[row(error = "Error")]
+struct PlaylistId(#[row(column = "playlist_id")] String);
+
+const MIGRATIONS: &str = include_str!("../../migrations.sql");
+const UPGRADES: &str = include_str!("../../upgrades.sql");
+
+/// Version of the database schema with all upgrades applied
+pub fn schema_version() -> usize {
+    spin_sqlite_connection::schema_version(UPGRADES)
+}
+
+/// Labels of the databases this instance already brought up to date
+static UPGRADED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn upgraded(label: &str) -> bool {
+    UPGRADED
+        .lock()
+        .map(|labels| labels.contains(label))
+        .unwrap_or_default()
+}
+
+pub struct Connection(SqliteConnection<Error>);
+
+/// The rows of the host are read through the connection they came from,
+/// so the stream owns the connection and closes it when the stream is dropped
+struct PlaylistStream {
+    playlists: BoxStream<'static, Result<Playlist>>,
+    _connection: Connection,
+}
+
+impl Stream for PlaylistStream {
+    type Item = Result<Playlist>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.playlists.poll_next_unpin(cx)
+    }
+}
+
+impl Connection {
+    /// The database of the Spin host with `label`.
+    /// The schema is created and upgraded on the first open of each database by this instance.
+    pub async fn open(label: &str, context: &Context) -> Result<Self> {
+        let upgraded = upgraded(label);
+        let connection = SqliteConnection::open(label, (!upgraded).then_some(MIGRATIONS))
+            .await
+            .map(|connection| Self::new(connection, context))?;
+        message::db_connection_established(context, "sqlite db", label);
+        if !upgraded {
+            connection.0.upgrade(UPGRADES).await?;
+            if let Ok(mut labels) = UPGRADED.lock() {
+                labels.insert(label.to_owned());
+            }
+        }
+        connection.foreign_keys_on().await
+    }
+
+    /// A connection to other storage than the Spin host, like a native database in tests.
+    /// The schema is created and upgraded on every open, because the storage may be new.
+    pub async fn try_open(storage: impl Storage + 'static, context: &Context) -> Result<Self> {
+        let connection = SqliteConnection::try_open(storage, Some(MIGRATIONS))
+            .await
+            .map(|connection| Self::new(connection, context))?;
+        connection.0.upgrade(UPGRADES).await?;
+        connection.foreign_keys_on().await
+    }
+
+    fn new(connection: SqliteConnection<Error>, context: &Context) -> Self {
+        let context = context.clone();
+        Self(connection.with_rollback_failure(move |error, rollback| {
+            message::rollback_failure(&context, error, rollback)
+        }))
+    }
+
+    async fn foreign_keys_on(self) -> Result<Self> {
+        self.0.execute(sql::SQL_FOREIGN_KEYS_ON, params![]).await?;
+        Ok(self)
+    }
+
+    /// Runs `f` in a transaction, nested transactions are savepoints
+    async fn transaction<T>(&self, f: impl AsyncFnOnce() -> Result<T>) -> Result<T> {
+        self.0.transaction(async |_| f().await).await
+    }
+
+    pub async fn select_user(&self) -> Result<Vec<User>> {
+        self.0.query::<User>(sql::SQL_SELECT_USER, params![]).await
+    }
+
+    pub async fn select_tenant(&self) -> Result<Vec<Tenant>> {
+        self.0
+            .query::<Tenant>(sql::SQL_SELECT_TENANT_LIST, params![])
+            .await
+    }
+
+    pub async fn select_tenant_by_name(&self, name: &str) -> Result<Option<Tenant>> {
+        self.0
+            .query::<Tenant>(sql::SQL_SELECT_TENANT, params![name])
+            .await
+            .map(first)
+    }
+
+    pub async fn select_tenant_by_host(&self, host: &str) -> Result<Option<Tenant>> {
+        self.0
+            .query::<Tenant>(sql::SQL_SELECT_TENANT_BY_HOST, params![host])
+            .await
+            .map(first)
+    }
+
+    pub async fn insert_tenant(&self, tenant: &Tenant) -> Result<()> {
+        let mut violations = tenant.violations();
+        if self.select_tenant_by_name(&tenant.name).await?.is_some() {
+            violations.push(Violation::new("name", "already in use"));
+        }
+        if let Some(host) = &tenant.host
+            && self.select_tenant_by_host(host).await?.is_some()
+        {
+            violations.push(Violation::new("host", "already in use"));
+        }
+        valid(violations)?;
+        self.0
+            .execute(
+                sql::SQL_INSERT_TENANT,
+                params![tenant.name, tenant.host, tenant.username, tenant.password],
+            )
+            .await
+            .map(unit)
+    }
+
+    /// The database of the tenant is left alone
+    pub async fn delete_tenant(&self, name: &str) -> Result<bool> {
+        self.0
+            .execute(sql::SQL_DELETE_TENANT, params![name])
+            .await
+            .map(|c| c > 0)
+    }
+
+    pub async fn select_lyric(&self) -> Result<Vec<Lyric>> {
+        self.select_lyric_by_query(&LyricQuery::default()).await
+    }
+
+    /// All lyrics, converted one row at a time as they are read
+    pub async fn stream_lyric(&self) -> Result<impl Stream<Item = Result<Lyric>> + use<>> {
+        self.0
+            .query_stream::<Lyric>(
+                sql::SQL_SELECT_LYRIC_LIST,
+                lyric_query_params(&LyricQuery::default())?,
+            )
+            .await
+    }
+
+    pub async fn select_lyric_by_query(&self, query: &LyricQuery) -> Result<Vec<Lyric>> {
+        self.0
+            .query::<Lyric>(sql::SQL_SELECT_LYRIC_LIST, lyric_query_params(query)?)
+            .await
+    }
+
+    pub async fn select_lyric_by_id(&self, id: &str) -> Result<Option<Lyric>> {
+        self.0
+            .query::<Lyric>(sql::SQL_SELECT_LYRIC, params![id])
+            .await
+            .map(first)
+    }
+
+    /// The lyrics with one of the ids in a single query, in no particular order
+    pub async fn select_lyric_by_ids(&self, ids: &[&str]) -> Result<Vec<Lyric>> {
+        self.0
+            .query::<Lyric>(sql::SQL_SELECT_LYRIC_BY_IDS, params![json_array(ids)?])
+            .await
+    }
+
+    /// The playlists with the lyric as member lose it, and get a new etag
+    pub async fn delete_lyric(&self, id: &str) -> Result<bool> {
+        self.transaction(async || {
+            let playlists = self
+                .0
+                .query::<PlaylistId>(sql::SQL_SELECT_PLAYLIST_IDS_BY_LYRIC, params![id])
+                .await?;
+            let deleted = self.0.execute(sql::SQL_DELETE_LYRIC, params![id]).await? > 0;
+            for PlaylistId(playlist_id) in playlists {
+                self.touch_playlist(&playlist_id).await?;
+            }
+            Ok(deleted)
+        })
+        .await
+    }
+
+    pub async fn update_lyric(&self, lyric: &Lyric) -> Result<bool> {
+        self.check_lyric(lyric).await?;
+        self.transaction(async || self.rewrite_lyric_keeping_chords(lyric).await)
+            .await
+    }
+
+    async fn rewrite_lyric_keeping_chords(&self, lyric: &Lyric) -> Result<bool> {
+        match self.select_lyric_by_id(&lyric.id).await? {
+            Some(stored) if lyric.chords.is_none() => {
+                self.rewrite_lyric(&lyric.clone().keep_chords(&stored))
+                    .await
+            }
+            _ => self.rewrite_lyric(lyric).await,
+        }
+    }
+
+    /// Updates the lyric and its tags together
+    async fn rewrite_lyric(&self, lyric: &Lyric) -> Result<bool> {
+        self.transaction(async || {
+            let found = self
+                .0
+                .execute(sql::SQL_UPDATE_LYRIC, lyric_params(lyric))
+                .await
+                .map(|c| c > 0)?;
+            if found {
+                self.write_lyric_tags(&lyric.id, &lyric.tags).await?;
+            }
+            Ok(found)
+        })
+        .await
+    }
+
+    pub async fn insert_lyric(&self, lyric: &Lyric) -> Result<()> {
+        self.check_lyric(lyric).await?;
+        self.write_lyric(lyric).await
+    }
+
+    /// Inserts all lyrics or none, violations are reported per lyric like `lyrics[1].title`
+    pub async fn insert_lyrics(&self, lyrics: &[Lyric]) -> Result<()> {
+        let db = Db {
+            lyrics: lyrics.to_vec(),
+            playlists: vec![],
+        };
+        let mut violations = db.violations();
+        for (i, lyric) in lyrics.iter().enumerate() {
+            if self
+                .title_in_use(sql::SQL_SELECT_LYRIC_TITLE_IN_USE, &lyric.title, &lyric.id)
+                .await?
+            {
+                violations.push(Violation::duplicate_title(format!("lyrics[{i}].title")));
+            }
+        }
+        valid(violations)?;
+        self.transaction(async || {
+            for lyric in lyrics {
+                self.write_lyric(lyric).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Inserts the lyric and its tags together
+    async fn write_lyric(&self, lyric: &Lyric) -> Result<()> {
+        self.transaction(async || {
+            self.0
+                .execute(sql::SQL_INSERT_LYRIC, lyric_params(lyric))
+                .await?;
+            self.write_lyric_tags(&lyric.id, &lyric.tags).await
+        })
+        .await
+    }
+
+    /// Replaces the tags of a lyric, creating the tags that do not exist yet
+    async fn write_lyric_tags(&self, lyric_id: &str, tags: &[String]) -> Result<()> {
+        self.0
+            .execute(sql::SQL_DELETE_LYRIC_TAGS, params![lyric_id])
+            .await?;
+        if tags.is_empty() {
+            return Ok(());
+        }
+        let tags = tags
+            .iter()
+            .map(|tag| Tag::normalize(tag))
+            .collect::<Vec<_>>();
+        for tag in tags.iter() {
+            self.0
+                .execute(
+                    sql::SQL_INSERT_TAG_IF_MISSING,
+                    params![Uuid::default(), tag],
+                )
+                .await?;
+        }
+        self.0
+            .execute(
+                sql::SQL_INSERT_LYRIC_TAGS,
+                params![lyric_id, json_array(&tags)?],
+            )
+            .await
+            .map(unit)
+    }
+
+    pub async fn select_tag(&self) -> Result<Vec<Tag>> {
+        self.0
+            .query::<Tag>(sql::SQL_SELECT_TAG_LIST, params![])
+            .await
+    }
+
+    pub async fn select_tag_by_id(&self, id: &str) -> Result<Option<Tag>> {
+        self.0
+            .query::<Tag>(sql::SQL_SELECT_TAG, params![id])
+            .await
+            .map(first)
+    }
+
+    pub async fn insert_tag(&self, tag: &Tag) -> Result<()> {
+        let name = Tag::normalize(&tag.name);
+        self.check_tag(&tag.id, &name).await?;
+        self.0
+            .execute(sql::SQL_INSERT_TAG, params![tag.id, name])
+            .await
+            .map(unit)
+    }
+
+    pub async fn update_tag(&self, id: &str, tag: &TagPost) -> Result<bool> {
+        let name = Tag::normalize(&tag.name);
+        self.check_tag(id, &name).await?;
+        self.0
+            .execute(sql::SQL_UPDATE_TAG, params![name, id])
+            .await
+            .map(|c| c > 0)
+    }
+
+    pub async fn delete_tag(&self, id: &str) -> Result<bool> {
+        self.0
+            .execute(sql::SQL_DELETE_TAG, params![id])
+            .await
+            .map(|c| c > 0)
+    }
+
+    async fn check_tag(&self, id: &str, name: &str) -> Result<()> {
+        let mut violations = TagPost {
+            name: name.to_owned(),
+        }
+        .violations();
+        if self
+            .title_in_use(sql::SQL_SELECT_TAG_NAME_IN_USE, name, id)
+            .await?
+        {
+            violations.push(Violation::new("name", "already in use"));
+        }
+        valid(violations)
+    }
+
+    /// All playlists with their members in a single query
+    pub async fn select_playlist(&self) -> Result<Vec<Playlist>> {
+        self.0
+            .query::<Playlist>(sql::SQL_SELECT_PLAYLIST_LIST, params![])
+            .await
+    }
+
+    /// All playlists with their members, converted one row at a time as they are read
+    pub async fn stream_playlist(self) -> Result<impl Stream<Item = Result<Playlist>> + 'static> {
+        let playlists = self
+            .0
+            .query_stream::<Playlist>(sql::SQL_SELECT_PLAYLIST_LIST, params![])
+            .await?
+            .boxed();
+        Ok(PlaylistStream {
+            playlists,
+            _connection: self,
+        })
+    }
+
+    pub async fn select_playlist_by_id(&self, id: &str) -> Result<Option<Playlist>> {
+        self.0
+            .query::<Playlist>(sql::SQL_GET_PLAYLIST, params![id])
+            .await
+            .map(first)
+    }
+
+    pub async fn delete_playlist_by_id(&self, id: &str) -> Result<()> {
+        self.0
+            .execute(sql::SQL_DELETE_PLAYLIST, params![id])
+            .await
+            .map(unit)
+    }
+
+    pub async fn delete_members_by_playlist_id(&self, playlist_id: &str) -> Result<i64> {
+        self.0
+            .execute(sql::SQL_DELETE_MEMBER, params![playlist_id])
+            .await
+            .map(|_| 0)
+    }
+
+    pub async fn insert_members(&self, playlist_id: &str, members: &[Member]) -> Result<()> {
+        for (i, member) in members.iter().enumerate() {
+            self.0
+                .execute(
+                    sql::SQL_INSERT_MEMBER,
+                    member_params(playlist_id, member, ordering(i)),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn update_playlist(&self, playlist: &Playlist) -> Result<()> {
+        self.check_playlist(playlist).await?;
+        self.rewrite_playlist(playlist).await
+    }
+
+    /// Updates the playlist and replaces its members together
+    async fn rewrite_playlist(&self, playlist: &Playlist) -> Result<()> {
+        self.transaction(async || {
+            self.delete_members_by_playlist_id(&playlist.id).await?;
+            self.0
+                .execute(
+                    sql::SQL_UPDATE_PLAYLIST,
+                    params![playlist.title, Uuid::default(), playlist.id],
+                )
+                .await?;
+            self.insert_members(&playlist.id, &playlist.members).await
+        })
+        .await
+    }
+
+    /// Returns false if the lyric does not exist
+    pub async fn patch_lyric(
+        &self,
+        id: &str,
+        if_match: Option<&str>,
+        patch: &Patch,
+    ) -> Result<bool> {
+        self.transaction(async || {
+            let Some(lyric) = self.select_lyric_by_id(id).await? else {
+                return Ok(false);
+            };
+            let lyric = patched(lyric, |l| &l.id, if_match, patch)?;
+            self.check_lyric(&lyric).await?;
+            self.rewrite_lyric(&lyric).await.map(|_| true)
+        })
+        .await
+    }
+
+    /// Returns false if the playlist does not exist
+    pub async fn patch_playlist(
+        &self,
+        id: &str,
+        if_match: Option<&str>,
+        patch: &Patch,
+    ) -> Result<bool> {
+        self.transaction(async || {
+            let Some(playlist) = self.select_playlist_by_id(id).await? else {
+                return Ok(false);
+            };
+            let playlist = patched(playlist, |p| &p.id, if_match, patch)?;
+            self.check_playlist(&playlist).await?;
+            self.rewrite_playlist(&playlist).await.map(|_| true)
+        })
+        .await
+    }
+
+    pub async fn insert_playlist(&self, playlist: &Playlist) -> Result<()> {
+        self.check_playlist(playlist).await?;
+        self.write_playlist(playlist).await
+    }
+
+    /// Inserts the playlist and its members together
+    async fn write_playlist(&self, playlist: &Playlist) -> Result<()> {
+        self.transaction(async || {
+            self.0
+                .execute(
+                    sql::SQL_INSERT_PLAYLIST,
+                    params![playlist.id, playlist.title, Uuid::default()],
+                )
+                .await?;
+            self.insert_members(&playlist.id, &playlist.members).await
+        })
+        .await
+    }
+
+    async fn unknown_lyric_ids(&self, lyric_ids: &[&String]) -> Result<HashSet<String>> {
+        self.0
+            .query::<LyricId>(
+                sql::SQL_SELECT_UNKNOWN_LYRIC_IDS,
+                params![json_array(lyric_ids)?],
+            )
+            .await
+            .map(|ids| ids.into_iter().map(|id| id.0).collect())
+    }
+
+    async fn title_in_use(&self, sql: &str, title: &str, id: &str) -> Result<bool> {
+        self.0
+            .query::<LyricId>(sql, params![title, id])
+            .await
+            .map(|ids| !ids.is_empty())
+    }
+
+    /// Fails with the violations found, including titles already used by another lyric
+    async fn check_lyric(&self, lyric: &Lyric) -> Result<()> {
+        let mut violations = lyric.violations();
+        if self
+            .title_in_use(sql::SQL_SELECT_LYRIC_TITLE_IN_USE, &lyric.title, &lyric.id)
+            .await?
+        {
+            violations.push(Violation::duplicate_title("title"));
+        }
+        valid(violations)
+    }
+
+    /// Fails with the violations found, including unknown lyrics and titles already used by another playlist
+    async fn check_playlist(&self, playlist: &Playlist) -> Result<()> {
+        let mut violations = playlist.violations();
+        let lyric_ids = playlist
+            .members
+            .iter()
+            .map(|member| &member.lyric_id)
+            .collect::<Vec<_>>();
+        let unknown = self.unknown_lyric_ids(&lyric_ids).await?;
+        violations.extend(
+            lyric_ids
+                .iter()
+                .enumerate()
+                .filter(|(_, id)| unknown.contains(id.as_str()))
+                .map(|(i, _)| Violation::unknown_lyric(format!("members[{i}].lyric_id"))),
+        );
+        if self
+            .title_in_use(
+                sql::SQL_SELECT_PLAYLIST_TITLE_IN_USE,
+                &playlist.title,
+                &playlist.id,
+            )
+            .await?
+        {
+            violations.push(Violation::duplicate_title("title"));
+        }
+        valid(violations)
+    }
+
+    async fn check_member(&self, member: &Member) -> Result<()> {
+        let mut violations = member.violations();
+        if !self
+            .unknown_lyric_ids(&[&member.lyric_id])
+            .await?
+            .is_empty()
+        {
+            violations.push(Violation::unknown_lyric("lyric_id"));
+        }
+        valid(violations)
+    }
+
+    async fn member_count(&self, playlist_id: &str) -> Result<Option<usize>> {
+        self.select_playlist_by_id(playlist_id)
+            .await
+            .map(|playlist| playlist.map(|p| p.members.len()))
+    }
+
+    async fn touch_playlist(&self, playlist_id: &str) -> Result<()> {
+        self.0
+            .execute(
+                sql::SQL_TOUCH_PLAYLIST,
+                params![Uuid::default(), playlist_id],
+            )
+            .await
+            .map(unit)?;
+        self.update_playlist_list_etag().await
+    }
+
+    /// Adds `delta` to the ordering of the members with an ordering in `first..=last`.
+    /// Negating in between keeps the unique member index satisfied while rows are updated one by one.
+    async fn shift_members(
+        &self,
+        playlist_id: &str,
+        first: i64,
+        last: i64,
+        delta: i64,
+    ) -> Result<()> {
+        self.0
+            .execute(
+                sql::SQL_SHIFT_MEMBERS_NEGATED,
+                params![delta, playlist_id, first, last],
+            )
+            .await?;
+        self.0
+            .execute(sql::SQL_RESTORE_NEGATED_MEMBERS, params![playlist_id])
+            .await
+            .map(unit)
+    }
+
+    async fn set_member_ordering(&self, playlist_id: &str, from: i64, to: i64) -> Result<()> {
+        self.0
+            .execute(sql::SQL_SET_MEMBER_ORDERING, params![to, playlist_id, from])
+            .await
+            .map(unit)
+    }
+
+    /// The ordering of the member at zero based `position`, none if there is no member there.
+    /// Deleting a lyric also deletes its members, so orderings can have gaps.
+    async fn member_ordering(&self, playlist_id: &str, position: usize) -> Result<Option<i64>> {
+        self.0
+            .query::<MemberOrdering>(
+                sql::SQL_SELECT_MEMBER_ORDERING_AT,
+                params![playlist_id, offset(position)],
+            )
+            .await
+            .map(|orderings| orderings.first().map(|o| o.0))
+    }
+
+    async fn last_member_ordering(&self, playlist_id: &str) -> Result<i64> {
+        self.0
+            .query::<MemberOrdering>(sql::SQL_SELECT_LAST_MEMBER_ORDERING, params![playlist_id])
+            .await
+            .map(|orderings| orderings.first().map(|o| o.0).unwrap_or_default())
+    }
+
+    /// Returns false if the playlist does not exist
+    pub async fn insert_member(&self, playlist_id: &str, member: &MemberPost) -> Result<bool> {
+        self.transaction(async || {
+            let Some(count) = self.member_count(playlist_id).await? else {
+                return Ok(false);
+            };
+            let position = member.position.unwrap_or(count);
+            if position > count {
+                return Err(Error::Validation(vec![Violation::new(
+                    "position",
+                    format!("must be at most {count}"),
+                )]));
+            }
+            self.check_member(&member.member).await?;
+            let ordering = match self.member_ordering(playlist_id, position).await? {
+                Some(ordering) => {
+                    self.shift_members(playlist_id, ordering, i64::MAX, 1)
+                        .await?;
+                    ordering
+                }
+                None => self.last_member_ordering(playlist_id).await? + 1,
+            };
+            self.0
+                .execute(
+                    sql::SQL_INSERT_MEMBER,
+                    member_params(playlist_id, &member.member, ordering),
+                )
+                .await?;
+            self.touch_playlist(playlist_id).await.map(|_| true)
+        })
+        .await
+    }
+
+    /// Returns false if the playlist does not exist or has no member at `position`
+    pub async fn delete_member(&self, playlist_id: &str, position: usize) -> Result<bool> {
+        self.transaction(async || {
+            let Some(ordering) = self.member_ordering(playlist_id, position).await? else {
+                return Ok(false);
+            };
+            let deleted = self
+                .0
+                .execute(sql::SQL_DELETE_MEMBER_AT, params![playlist_id, ordering])
+                .await?
+                > 0;
+            if !deleted {
+                return Ok(false);
+            }
+            self.touch_playlist(playlist_id).await.map(|_| true)
+        })
+        .await
+    }
+
+    /// Applies the moves in order. Only the members between source and target of each move are renumbered.
+    /// Returns false if the playlist does not exist
+    pub async fn move_members(&self, playlist_id: &str, moves: &[MemberMove]) -> Result<bool> {
+        self.transaction(async || {
+            let Some(count) = self.member_count(playlist_id).await? else {
+                return Ok(false);
+            };
+            valid(member_move_violations(moves, count))?;
+            for MemberMove { from, to } in moves.iter().filter(|m| m.from != m.to) {
+                let (Some(from), Some(to)) = (
+                    self.member_ordering(playlist_id, *from).await?,
+                    self.member_ordering(playlist_id, *to).await?,
+                ) else {
+                    return Err(Error::Body);
+                };
+                self.set_member_ordering(playlist_id, from, 0).await?;
+                if from < to {
+                    self.shift_members(playlist_id, from + 1, to, -1).await?;
+                } else {
+                    self.shift_members(playlist_id, to, from - 1, 1).await?;
+                }
+                self.set_member_ordering(playlist_id, 0, to).await?;
+            }
+            self.touch_playlist(playlist_id).await.map(|_| true)
+        })
+        .await
+    }
+
+    pub async fn update_lyric_list_etag(&self) -> Result<()> {
+        self.0
+            .execute(sql::SQL_UPDATE_LYRIC_LIST_ETAG, params![Uuid::default()])
+            .await
+            .map(unit)
+    }
+
+    pub async fn update_playlist_list_etag(&self) -> Result<()> {
+        self.0
+            .execute(sql::SQL_UPDATE_PLAYLIST_LIST_ETAG, params![Uuid::default()])
+            .await
+            .map(unit)
+    }
+
+    async fn delete_all(&self, sql: &str) -> Result<()> {
+        self.0.execute(sql, params![]).await.map(unit)
+    }
+
+    pub async fn delete_all_lyrics(&self) -> Result<()> {
+        self.delete_all(sql::SQL_DELETE_ALL_LYRICS).await
+    }
+
+    pub async fn delete_all_playlists(&self) -> Result<()> {
+        self.delete_all(sql::SQL_DELETE_ALL_PLAYLISTS).await
+    }
+
+    pub async fn delete_all_members(&self) -> Result<()> {
+        self.delete_all(sql::SQL_DELETE_ALL_MEMBERS).await
+    }
+
+    pub async fn replace_db(&self, db: &Db) -> Result<()> {
+        valid(db.violations())?;
+        self.transaction(async || {
+            self.delete_all_playlists().await?;
+            self.delete_all_lyrics().await?;
+            self.delete_all_members().await?;
+            for lyric in db.lyrics.iter() {
+                self.write_lyric(lyric).await?;
+            }
+            for playlist in db.playlists.iter() {
+                self.write_playlist(playlist).await?;
+            }
+            self.update_lyric_list_etag().await?;
+            self.update_playlist_list_etag().await
+        })
+        .await
+    }
+}
+
+mod sql {
+    pub const SQL_FOREIGN_KEYS_ON: &str = "PRAGMA foreign_keys = ON";
+
+    pub const SQL_SELECT_LYRIC_LIST: &str = "SELECT id, title, parts, created, modified, etag, artist, composer, copyright, key, tempo, language, duration, notes, (SELECT json_group_array(name) FROM (SELECT tag.name FROM lyric_tag JOIN tag ON tag.id = lyric_tag.tag_id WHERE lyric_tag.lyric_id = lyric.id ORDER BY tag.name)) AS tags FROM lyric WHERE (:artist IS NULL OR artist = :artist COLLATE NOCASE) AND (:composer IS NULL OR composer = :composer COLLATE NOCASE) AND (:key IS NULL OR key = :key COLLATE NOCASE) AND (:language IS NULL OR language = :language COLLATE NOCASE) AND (SELECT COUNT(*) FROM lyric_tag JOIN tag ON tag.id = lyric_tag.tag_id WHERE lyric_tag.lyric_id = lyric.id AND tag.name IN (SELECT value FROM json_each(:tags))) = (SELECT COUNT(DISTINCT value) FROM json_each(:tags)) ORDER BY title";
+    pub const SQL_SELECT_LYRIC: &str = "SELECT id, title, parts, created, modified, etag, artist, composer, copyright, key, tempo, language, duration, notes, (SELECT json_group_array(name) FROM (SELECT tag.name FROM lyric_tag JOIN tag ON tag.id = lyric_tag.tag_id WHERE lyric_tag.lyric_id = lyric.id ORDER BY tag.name)) AS tags FROM lyric WHERE Id=?";
+    pub const SQL_SELECT_LYRIC_BY_IDS: &str = "SELECT id, title, parts, created, modified, etag, artist, composer, copyright, key, tempo, language, duration, notes, (SELECT json_group_array(name) FROM (SELECT tag.name FROM lyric_tag JOIN tag ON tag.id = lyric_tag.tag_id WHERE lyric_tag.lyric_id = lyric.id ORDER BY tag.name)) AS tags FROM lyric WHERE id IN (SELECT value FROM json_each(?))";
+    pub const SQL_INSERT_LYRIC: &str = "INSERT INTO lyric (id, title, parts, created, modified, etag, artist, composer, copyright, key, tempo, language, duration, notes) VALUES (:id, :title, :parts, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), :etag, :artist, :composer, :copyright, :key, :tempo, :language, :duration, :notes)";
+    pub const SQL_UPDATE_LYRIC: &str = "UPDATE lyric SET title=:title, parts=:parts, artist=:artist, composer=:composer, copyright=:copyright, key=:key, tempo=:tempo, language=:language, duration=:duration, notes=:notes, modified=strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE Id=:id";
+    pub const SQL_DELETE_LYRIC: &str = "DELETE FROM lyric WHERE Id=?";
+    pub const SQL_SELECT_PLAYLIST_IDS_BY_LYRIC: &str =
+        "SELECT DISTINCT playlist_id FROM member WHERE lyric_id = ?";
+    pub const SQL_SELECT_LYRIC_TITLE_IN_USE: &str =
+        "SELECT id FROM lyric WHERE title = ? AND id <> ?";
+    pub const SQL_SELECT_UNKNOWN_LYRIC_IDS: &str =
+        "SELECT DISTINCT value AS id FROM json_each(?) WHERE value NOT IN (SELECT id FROM lyric)";
+
+    pub const SQL_DELETE_LYRIC_TAGS: &str = "DELETE FROM lyric_tag WHERE lyric_id = ?";
+    pub const SQL_INSERT_LYRIC_TAGS: &str = "INSERT INTO lyric_tag (lyric_id, tag_id) SELECT ?, id FROM tag WHERE name IN (SELECT value FROM json_each(?))";
+
+    pub const SQL_SELECT_TAG_LIST: &str = "SELECT tag.id, tag.name, COUNT(lyric_tag.lyric_id) AS count FROM tag LEFT JOIN lyric_tag ON lyric_tag.tag_id = tag.id GROUP BY tag.id ORDER BY tag.name";
+    pub const SQL_SELECT_TAG: &str = "SELECT tag.id, tag.name, COUNT(lyric_tag.lyric_id) AS count FROM tag LEFT JOIN lyric_tag ON lyric_tag.tag_id = tag.id WHERE tag.id = ? GROUP BY tag.id";
+    pub const SQL_INSERT_TAG: &str = "INSERT INTO tag (id, name) VALUES (?, ?)";
+    pub const SQL_INSERT_TAG_IF_MISSING: &str =
+        "INSERT INTO tag (id, name) VALUES (?, ?) ON CONFLICT(name) DO NOTHING";
+    pub const SQL_UPDATE_TAG: &str = "UPDATE tag SET name = ? WHERE id = ?";
+    pub const SQL_DELETE_TAG: &str = "DELETE FROM tag WHERE id = ?";
+    pub const SQL_SELECT_TAG_NAME_IN_USE: &str = "SELECT id FROM tag WHERE name = ? AND id <> ?";
+
+    // The members of a playlist in order as a json array, so a playlist is read in a single row
+    pub const SQL_SELECT_PLAYLIST_LIST: &str = "SELECT id, title, created, modified, etag, (SELECT json_group_array(json_object('lyric_id', lyric_id, 'key', key, 'repeat', repeat, 'notes', notes, 'segment', segment)) FROM (SELECT lyric_id, key, repeat, notes, segment FROM member WHERE member.playlist_id = playlist.id ORDER BY ordering)) AS members FROM playlist ORDER BY title";
+    pub const SQL_GET_PLAYLIST: &str = "SELECT id, title, created, modified, etag, (SELECT json_group_array(json_object('lyric_id', lyric_id, 'key', key, 'repeat', repeat, 'notes', notes, 'segment', segment)) FROM (SELECT lyric_id, key, repeat, notes, segment FROM member WHERE member.playlist_id = playlist.id ORDER BY ordering)) AS members FROM playlist WHERE id = ?";
+
+    pub const SQL_INSERT_PLAYLIST: &str = "INSERT INTO playlist (id, title, created, modified, etag) VALUES (?, ?, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), ?)";
+    pub const SQL_UPDATE_PLAYLIST: &str = "UPDATE playlist SET title = ?, modified = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), etag = ? WHERE id = ?";
+    pub const SQL_TOUCH_PLAYLIST: &str = "UPDATE playlist SET modified = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), etag = ? WHERE id = ?";
+    pub const SQL_DELETE_PLAYLIST: &str = "DELETE FROM playlist WHERE Id=?";
+    pub const SQL_SELECT_PLAYLIST_TITLE_IN_USE: &str =
+        "SELECT id FROM playlist WHERE title = ? AND id <> ?";
+
+    pub const SQL_INSERT_MEMBER: &str = "INSERT INTO member (playlist_id, lyric_id, ordering, key, repeat, notes, segment) VALUES (:playlist_id, :lyric_id, :ordering, :key, :repeat, :notes, :segment)";
+    pub const SQL_DELETE_MEMBER: &str = "DELETE FROM member WHERE playlist_id = ?";
+    pub const SQL_SELECT_MEMBER_ORDERING_AT: &str =
+        "SELECT ordering FROM member WHERE playlist_id = ? ORDER BY ordering LIMIT 1 OFFSET ?";
+    pub const SQL_SELECT_LAST_MEMBER_ORDERING: &str =
+        "SELECT COALESCE(MAX(ordering), 0) AS ordering FROM member WHERE playlist_id = ?";
+    pub const SQL_DELETE_MEMBER_AT: &str =
+        "DELETE FROM member WHERE playlist_id = ? AND ordering = ?";
+    pub const SQL_SET_MEMBER_ORDERING: &str =
+        "UPDATE member SET ordering = ? WHERE playlist_id = ? AND ordering = ?";
+    pub const SQL_SHIFT_MEMBERS_NEGATED: &str = "UPDATE member SET ordering = -(ordering + ?) WHERE playlist_id = ? AND ordering BETWEEN ? AND ?";
+    pub const SQL_RESTORE_NEGATED_MEMBERS: &str =
+        "UPDATE member SET ordering = -ordering WHERE playlist_id = ? AND ordering < 0";
+
+    pub const SQL_UPDATE_LYRIC_LIST_ETAG: &str =
+        "UPDATE list_etag SET etag = ? WHERE id = 'lyrics'";
+    pub const SQL_UPDATE_PLAYLIST_LIST_ETAG: &str =
+        "UPDATE list_etag SET etag = ? WHERE id = 'playlists'";
+
+    pub const SQL_DELETE_ALL_PLAYLISTS: &str = "DELETE FROM playlist";
+    pub const SQL_DELETE_ALL_LYRICS: &str = "DELETE FROM lyric";
+    pub const SQL_DELETE_ALL_MEMBERS: &str = "DELETE FROM member";
+
+    pub const SQL_SELECT_TENANT_LIST: &str =
+        "SELECT name, host, username, password FROM tenant ORDER BY name";
+    pub const SQL_SELECT_TENANT: &str =
+        "SELECT name, host, username, password FROM tenant WHERE name = ?";
+    pub const SQL_SELECT_TENANT_BY_HOST: &str =
+        "SELECT name, host, username, password FROM tenant WHERE host = ?";
+    pub const SQL_INSERT_TENANT: &str =
+        "INSERT INTO tenant (name, host, username, password) VALUES (?, ?, ?, ?)";
+    pub const SQL_DELETE_TENANT: &str = "DELETE FROM tenant WHERE name = ?";
+
+    pub const SQL_SELECT_USER: &str = "SELECT id, name, password FROM user";
+}
+
+#[cfg(test)]
+mod query_count {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use futures::{executor::block_on, future::BoxFuture};
+    use model::{Lyric, LyricQuery, Playlist, Uuid, member::Member};
+    use rusqlite::Connection;
+    use spin_sdk::sqlite::{Error, Value};
+    use spin_sqlite_connection::{NativeStorage, Params, Rows, Storage};
+
+    use super::{MIGRATIONS, UPGRADES, lyric_query_params, sql};
+    use crate::context::Context;
+
+    fn open() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS).unwrap();
+        connection.execute_batch(UPGRADES).unwrap();
+        connection
+            .execute(
+                "INSERT INTO lyric (id, title, parts, created, modified, etag) VALUES ('a', 'Sofietje', '', '', '', 'a')",
+                (),
+            )
+            .unwrap();
+        connection
+    }
+
+    /// The statement of `stream_lyric`, every named parameter of the lyric list needs a value
+    #[test]
+    fn lyric_list_binds_every_filter() {
+        let connection = open();
+        let Params::Named(values) = lyric_query_params(&LyricQuery::default()).unwrap() else {
+            panic!("named parameters expected");
+        };
+        let values = values
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::Text(text) => rusqlite::types::Value::Text(text),
+                    _ => rusqlite::types::Value::Null,
+                };
+                (name, value)
+            })
+            .collect::<Vec<_>>();
+        let named = values
+            .iter()
+            .map(|(name, value)| (*name, value as &dyn rusqlite::ToSql))
+            .collect::<Vec<_>>();
+        let mut statement = connection.prepare(sql::SQL_SELECT_LYRIC_LIST).unwrap();
+        for index in 1..=statement.parameter_count() {
+            let name = statement.parameter_name(index).unwrap();
+            assert!(values.iter().any(|(n, _)| *n == name), "{name}");
+        }
+        let titles = statement
+            .query_map(named.as_slice(), |row| row.get::<_, String>("title"))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(titles, vec!["Sofietje"]);
+    }
+
+    fn select_playlist(connection: &Connection) -> Vec<(String, Vec<Member>)> {
+        let mut statement = connection.prepare(sql::SQL_SELECT_PLAYLIST_LIST).unwrap();
+        statement
+            .query_map((), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    serde_json::from_str(&row.get::<_, String>(5)?).unwrap(),
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    /// Counts the statements that reach the database
+    struct Counting {
+        storage: NativeStorage,
+        queries: Arc<AtomicUsize>,
+    }
+
+    impl Storage for Counting {
+        fn query<'a>(
+            &'a self,
+            sql: &'a str,
+            parameters: Vec<Value>,
+        ) -> BoxFuture<'a, std::result::Result<(Vec<String>, Rows), Error>> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            self.storage.query(sql, parameters)
+        }
+
+        fn changes(&self) -> BoxFuture<'_, u64> {
+            self.storage.changes()
+        }
+    }
+
+    /// A connection with three lyrics and `playlists` playlists with the lyrics in reverse,
+    /// the counter of its statements and the lyrics
+    async fn counted(playlists: usize) -> (super::Connection, Arc<AtomicUsize>, Vec<Lyric>) {
+        let queries = Arc::new(AtomicUsize::new(0));
+        let connection = super::Connection::try_open(
+            Counting {
+                storage: NativeStorage::open_in_memory().unwrap(),
+                queries: queries.clone(),
+            },
+            &Context::new(None),
+        )
+        .await
+        .unwrap();
+        let lyrics = ["Sofietje", "Dodenrit", "Kerstmis"]
+            .map(|title| Lyric::new(Uuid::default().to_string(), title.to_owned(), vec![]))
+            .to_vec();
+        connection.insert_lyrics(&lyrics).await.unwrap();
+        for i in 0..playlists {
+            let members = lyrics
+                .iter()
+                .rev()
+                .map(|lyric| lyric.id.as_str().into())
+                .collect();
+            let playlist = Playlist::new(i.to_string(), format!("Playlist {i}"), members);
+            connection.insert_playlist(&playlist).await.unwrap();
+        }
+        queries.store(0, Ordering::SeqCst);
+        (connection, queries, lyrics)
+    }
+
+    #[test]
+    fn constant_for_any_number_of_playlists() {
+        block_on(async {
+            for size in [1, 10, 100] {
+                let (connection, queries, lyrics) = counted(size).await;
+                let reversed = lyrics
+                    .iter()
+                    .rev()
+                    .map(|lyric| lyric.id.as_str())
+                    .collect::<Vec<_>>();
+                let members = |playlist: &Playlist| {
+                    playlist
+                        .members
+                        .iter()
+                        .map(|member| member.lyric_id.clone())
+                        .collect::<Vec<_>>()
+                };
+                let playlists = connection.select_playlist().await.unwrap();
+                assert_eq!(queries.swap(0, Ordering::SeqCst), 1, "{size} playlists");
+                assert_eq!(playlists.len(), size);
+                for playlist in playlists.iter() {
+                    assert_eq!(members(playlist), reversed);
+                }
+                let playlist = connection
+                    .select_playlist_by_id(&playlists[0].id)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(queries.swap(0, Ordering::SeqCst), 1, "{size} playlists");
+                assert_eq!(members(&playlist), reversed);
+            }
+        });
+    }
+
+    #[test]
+    fn member_lyrics_in_one_statement() {
+        block_on(async {
+            let (connection, queries, lyrics) = counted(0).await;
+            let id = lyrics[0].id.as_str();
+            let selected = connection
+                .select_lyric_by_ids(&[id, id, "missing"])
+                .await
+                .unwrap();
+            assert_eq!(queries.load(Ordering::SeqCst), 1);
+            assert_eq!(
+                selected
+                    .iter()
+                    .map(|lyric| lyric.title.as_str())
+                    .collect::<Vec<_>>(),
+                vec!["Sofietje"]
+            );
+        });
+    }
+
+    #[test]
+    fn playlist_without_members() {
+        let connection = open();
+        connection
+            .execute(
+                "INSERT INTO playlist (id, title, created, modified, etag) VALUES ('p', 'Leeg', '', '', 'p')",
+                (),
+            )
+            .unwrap();
+        assert_eq!(select_playlist(&connection), vec![("p".to_owned(), vec![])]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use futures::executor::block_on;
+    use model::{Db, Lyric, Playlist, Tag, Tenant, Uuid, error::Error};
+    use spin_sqlite_connection::{FromRow, NativeStorage, params};
+
+    use super::Connection;
+    use crate::context::Context;
+
+    async fn open() -> Connection {
+        Connection::try_open(
+            NativeStorage::open_in_memory().unwrap(),
+            &Context::new(None),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn lyric(title: &str) -> Lyric {
+        Lyric::new(Uuid::default().to_string(), title.to_owned(), vec![])
+    }
+
+    #[test]
+    fn show_tables() {
+        #[derive(FromRow)]
+        #[row(error = "Error")]
+        struct Table {
+            name: String,
+        }
+
+        block_on(async {
+            let connection = open().await;
+            let mut names = connection
+                .0
+                .query::<Table>(
+                    "SELECT name FROM sqlite_schema WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+                    params![],
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|table| table.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            assert_eq!(
+                names,
+                vec![
+                    "list_etag",
+                    "lyric",
+                    "lyric_tag",
+                    "member",
+                    "playlist",
+                    "tag",
+                    "tenant",
+                    "user"
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn tenant_credentials() {
+        block_on(async {
+            let connection = open().await;
+            let tenant = Tenant {
+                name: "koor".to_owned(),
+                host: Some("koor.example.org".to_owned()),
+                username: "koor".to_owned(),
+                password: "zingen".to_owned(),
+            };
+            connection.insert_tenant(&tenant).await.unwrap();
+            assert_eq!(
+                connection
+                    .select_tenant_by_host("KOOR.example.org")
+                    .await
+                    .unwrap(),
+                Some(tenant.clone())
+            );
+            assert_eq!(connection.select_tenant().await.unwrap(), vec![tenant]);
+        });
+    }
+
+    #[test]
+    fn insert_lyric() {
+        block_on(async {
+            let connection = open().await;
+            let mut lyric = lyric("Zie maar hoe je het doet");
+            connection.insert_lyric(&lyric).await.unwrap();
+
+            let stored = connection
+                .select_lyric_by_id(&lyric.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(stored.created.is_some());
+            assert!(stored.modified.is_some());
+            assert!(stored.etag.is_some());
+
+            "Hallo allemaal".clone_into(&mut lyric.title);
+            thread::sleep(Duration::from_millis(5));
+            assert!(connection.update_lyric(&lyric).await.unwrap());
+            let updated = connection
+                .select_lyric_by_id(&lyric.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(updated.title, "Hallo allemaal");
+            assert_eq!(updated.created, stored.created);
+            assert!(updated.modified > stored.modified);
+        });
+    }
+
+    #[test]
+    fn insert_playlist() {
+        block_on(async {
+            let connection = open().await;
+            let lyric = lyric("Zie maar hoe je het doet");
+            connection.insert_lyric(&lyric).await.unwrap();
+
+            let playlist = Playlist::new(
+                Uuid::default().to_string(),
+                "Alles".to_owned(),
+                vec![lyric.id.as_str().into()],
+            );
+            connection.insert_playlist(&playlist).await.unwrap();
+            let stored = connection
+                .select_playlist_by_id(&playlist.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(stored.etag.is_some());
+            assert_eq!(stored.members, playlist.members);
+
+            connection.delete_lyric(&lyric.id).await.unwrap();
+            let without_lyric = connection
+                .select_playlist_by_id(&playlist.id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(without_lyric.members.is_empty());
+        });
+    }
+
+    #[test]
+    fn failed_transaction_is_rolled_back() {
+        block_on(async {
+            let connection = open().await;
+            let tag = Tag {
+                id: Uuid::default().to_string(),
+                name: "kerst".to_owned(),
+                count: 0,
+            };
+            let result = connection
+                .transaction(async || {
+                    connection.insert_tag(&tag).await?;
+                    Err::<(), _>(Error::Body)
+                })
+                .await;
+            assert!(matches!(result, Err(Error::Body)));
+            assert!(connection.select_tag().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn replace_db() {
+        block_on(async {
+            let connection = open().await;
+            let lyrics = vec![lyric("Sofietje"), lyric("Dodenrit")];
+            let db = Db {
+                playlists: vec![Playlist::new(
+                    Uuid::default().to_string(),
+                    "Kerst".to_owned(),
+                    lyrics
+                        .iter()
+                        .map(|lyric| lyric.id.as_str().into())
+                        .collect(),
+                )],
+                lyrics,
+            };
+            connection.replace_db(&db).await.unwrap();
+            assert_eq!(connection.select_lyric().await.unwrap().len(), 2);
+            let playlists = connection.select_playlist().await.unwrap();
+            assert_eq!(playlists.len(), 1);
+            assert_eq!(playlists[0].members, db.playlists[0].members);
+        });
+    }
+}
+
+#[cfg(test)]
+mod members {
+    use futures::executor::block_on;
+    use model::{Lyric, MemberMove, MemberPost, Playlist, Uuid, member::Member};
+    use spin_sqlite_connection::NativeStorage;
+
+    use super::Connection;
+    use crate::context::Context;
+
+    fn lyric_ids(connection: &Connection, playlist_id: &str) -> Vec<String> {
+        block_on(connection.select_playlist_by_id(playlist_id))
+            .unwrap()
+            .unwrap()
+            .members
+            .into_iter()
+            .map(|member| member.lyric_id)
+            .collect()
+    }
+
+    /// Deleting a lyric leaves a gap in the orderings of the members of its playlists
+    #[test]
+    fn playlist_with_gaps() {
+        let connection = block_on(Connection::try_open(
+            NativeStorage::open_in_memory().unwrap(),
+            &Context::new(None),
+        ))
+        .unwrap();
+        let ids = (0..5)
+            .map(|_| Uuid::default().to_string())
+            .collect::<Vec<_>>();
+        for (id, title) in ids.iter().zip(["A", "B", "C", "D", "E"]) {
+            let lyric = Lyric::new(id.clone(), title.to_owned(), vec![vec![title.to_owned()]]);
+            block_on(connection.insert_lyric(&lyric)).unwrap();
+        }
+        let [a, b, c, d, e]: [&str; 5] = ids
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        let playlist_id = Uuid::default().to_string();
+        let members = [a, b, c, d].map(Member::from).to_vec();
+        block_on(connection.insert_playlist(&Playlist::new(
+            playlist_id.clone(),
+            "Playlist".to_owned(),
+            members,
+        )))
+        .unwrap();
+
+        assert!(block_on(connection.delete_lyric(b)).unwrap());
+        assert_eq!(lyric_ids(&connection, &playlist_id), [a, c, d]);
+
+        let moves = [MemberMove { from: 2, to: 0 }];
+        assert!(block_on(connection.move_members(&playlist_id, &moves)).unwrap());
+        assert_eq!(lyric_ids(&connection, &playlist_id), [d, a, c]);
+
+        let member = MemberPost {
+            member: e.into(),
+            position: Some(1),
+        };
+        assert!(block_on(connection.insert_member(&playlist_id, &member)).unwrap());
+        assert_eq!(lyric_ids(&connection, &playlist_id), [d, e, a, c]);
+
+        assert!(block_on(connection.delete_member(&playlist_id, 2)).unwrap());
+        assert_eq!(lyric_ids(&connection, &playlist_id), [d, e, c]);
+        assert!(!block_on(connection.delete_member(&playlist_id, 3)).unwrap());
+
+        let member = MemberPost {
+            member: a.into(),
+            position: None,
+        };
+        assert!(block_on(connection.insert_member(&playlist_id, &member)).unwrap());
+        assert_eq!(lyric_ids(&connection, &playlist_id), [d, e, c, a]);
+    }
+}
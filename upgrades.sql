@@ -0,0 +1,20 @@
+ALTER TABLE member ADD COLUMN key TEXT;
+ALTER TABLE member ADD COLUMN repeat INTEGER;
+ALTER TABLE member ADD COLUMN notes TEXT;
+ALTER TABLE member ADD COLUMN segment TEXT;
+ALTER TABLE lyric ADD COLUMN artist TEXT;
+ALTER TABLE lyric ADD COLUMN composer TEXT;
+ALTER TABLE lyric ADD COLUMN copyright TEXT;
+ALTER TABLE lyric ADD COLUMN key TEXT;
+ALTER TABLE lyric ADD COLUMN tempo INTEGER;
+ALTER TABLE lyric ADD COLUMN language TEXT;
+ALTER TABLE lyric ADD COLUMN duration INTEGER;
+ALTER TABLE lyric ADD COLUMN notes TEXT;
+CREATE TABLE IF NOT EXISTS tag(id TEXT NOT NULL PRIMARY KEY, name TEXT NOT NULL);
+CREATE UNIQUE INDEX IF NOT EXISTS tag_name on tag (name);
+CREATE TABLE IF NOT EXISTS lyric_tag(lyric_id TEXT NOT NULL REFERENCES lyric(id) ON DELETE CASCADE, tag_id TEXT NOT NULL REFERENCES tag(id) ON DELETE CASCADE, PRIMARY KEY (lyric_id, tag_id));
+CREATE TABLE IF NOT EXISTS tenant(name TEXT NOT NULL PRIMARY KEY, host TEXT UNIQUE COLLATE NOCASE);
+ALTER TABLE tenant ADD COLUMN username TEXT NOT NULL DEFAULT '';
+ALTER TABLE tenant ADD COLUMN password TEXT NOT NULL DEFAULT '';
+DROP INDEX IF EXISTS member_lyric_playlist;
+CREATE UNIQUE INDEX IF NOT EXISTS member_playlist_ordering on member (playlist_id, ordering);